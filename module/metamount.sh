@@ -27,4 +27,6 @@ log "Hybrid Mount exited with code $EXIT_CODE"
 if [ "$EXIT_CODE" = "0" ]; then
     /data/adb/ksud kernel notify-module-mounted
 fi
+# Ratoon counter is only cleared once Android reports boot completion
+nohup "$BINARY" watchdog >> "$LOG_FILE" 2>&1 &
 exit $EXIT_CODE
\ No newline at end of file
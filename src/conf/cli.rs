@@ -9,7 +9,6 @@ use super::config::CONFIG_FILE_DEFAULT;
 
 #[derive(Parser, Debug)]
 #[command(name = "meta-hybrid", version, about = "Hybrid Mount Metamodule")]
-
 pub struct Cli {
     #[arg(short = 'c', long = "config")]
     pub config: Option<PathBuf>,
@@ -28,7 +27,6 @@ pub struct Cli {
 }
 
 #[derive(Subcommand, Debug)]
-
 pub enum Commands {
     GenConfig {
         #[arg(short = 'o', long = "output", default_value = CONFIG_FILE_DEFAULT)]
@@ -48,9 +46,37 @@ pub enum Commands {
         payload: String,
     },
     Storage,
+    Status {
+        #[arg(long)]
+        mounts: bool,
+    },
     Modules,
     Conflicts,
     Diagnostics,
+    Verify,
+    Apply {
+        #[arg(long)]
+        module: String,
+    },
+    /// Re-sync modules into the live storage and remount them. Without
+    /// `--force` modules whose storage copy is current are left alone.
+    Sync {
+        #[arg(long)]
+        force: bool,
+        #[arg(value_name = "ID", required = true)]
+        modules: Vec<String>,
+    },
+    #[command(name = "teardown", alias = "unmount")]
+    Teardown,
+    Watchdog,
+    Granary {
+        #[command(subcommand)]
+        command: GranaryCommand,
+    },
+    Profile {
+        #[command(subcommand)]
+        command: ProfileCommand,
+    },
     #[command(name = "system-action")]
     SystemAction {
         #[arg(long)]
@@ -59,3 +85,61 @@ pub enum Commands {
         value: Option<String>,
     },
 }
+
+#[derive(Subcommand, Debug)]
+pub enum GranaryCommand {
+    /// Compare two silos; use `current` for the live configuration.
+    Diff {
+        from: String,
+        to: String,
+        #[arg(long)]
+        json: bool,
+    },
+    /// Restore a silo, or only the selected parts of it. The live setup is
+    /// saved as a new silo first.
+    Restore {
+        id: String,
+        #[arg(long)]
+        winnowing: bool,
+        #[arg(long)]
+        partitions: bool,
+        /// Restore the rule files of these modules.
+        #[arg(long, value_name = "MODULE", value_delimiter = ',')]
+        rules: Vec<String>,
+    },
+    /// Report silo files that are damaged or fail their checksum.
+    Check {
+        #[arg(long)]
+        json: bool,
+    },
+    /// Write a silo (or `current`) to a portable archive.
+    Export { id: String, file: PathBuf },
+    /// Add a silo from an archive to the granary.
+    Import {
+        file: PathBuf,
+        /// Rename partitions as `old=new`; `old=` drops them.
+        #[arg(long, value_name = "OLD=NEW", value_delimiter = ',')]
+        remap: Vec<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ProfileCommand {
+    /// List the configured profiles and which one is active.
+    List {
+        #[arg(long)]
+        json: bool,
+    },
+    /// Switch to a profile; it takes effect on the next boot.
+    Use {
+        name: String,
+        /// Remount the affected modules right away.
+        #[arg(long)]
+        apply: bool,
+    },
+    /// Deactivate the current profile.
+    Clear {
+        #[arg(long)]
+        apply: bool,
+    },
+}
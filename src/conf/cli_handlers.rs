@@ -1,22 +1,24 @@
 // Copyright 2025 Meta-Hybrid Mount Authors
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use std::path::Path;
+use std::{collections::HashMap, path::Path};
 
 use anyhow::{Context, Result, bail};
 use serde::Serialize;
 
 use crate::{
     conf::{
-        cli::Cli,
+        cli::{Cli, GranaryCommand, ProfileCommand},
         config::{CONFIG_FILE_DEFAULT, Config},
     },
-    core::{executor, granary, inventory, modules, planner, storage, winnow},
-    utils,
+    core::{
+        bisect, executor, graft, granary, inventory, modules, planner, profile, silo_archive,
+        silo_diff, state::RuntimeState, storage, sync, verify, winnow,
+    },
+    defs, utils,
 };
 
 #[derive(Serialize)]
-
 struct DiagnosticIssueJson {
     level: String,
     context: String,
@@ -54,6 +56,16 @@ fn load_config(cli: &Cli) -> Result<Config> {
     }
 }
 
+/// Config as the mount pipeline sees it, with the active profile applied.
+/// Never save it back.
+fn load_mount_config(cli: &Cli) -> Result<Config> {
+    let mut config = load_config(cli)?;
+
+    config.apply_profile();
+
+    Ok(config)
+}
+
 pub fn handle_gen_config(output: &Path) -> Result<()> {
     Config::default()
         .save_to_file(output)
@@ -107,7 +119,7 @@ pub fn handle_save_rules(module: &str, payload: &str) -> Result<()> {
     let _: inventory::ModuleRules =
         serde_json::from_slice(&json_bytes).context("Invalid rules JSON")?;
 
-    let rules_dir = std::path::Path::new("/data/adb/meta-hybrid/rules");
+    let rules_dir = std::path::Path::new(defs::RULES_DIR);
 
     std::fs::create_dir_all(rules_dir).context("Failed to create rules directory")?;
 
@@ -125,14 +137,29 @@ pub fn handle_storage() -> Result<()> {
     storage::print_status().context("Failed to retrieve storage status")
 }
 
+pub fn handle_status(mounts: bool) -> Result<()> {
+    let state = RuntimeState::load().context("Failed to load runtime state")?;
+
+    let json = if mounts {
+        serde_json::to_string(&state.mounts)
+    } else {
+        serde_json::to_string(&state)
+    }
+    .context("Failed to serialize runtime state")?;
+
+    println!("{}", json);
+
+    Ok(())
+}
+
 pub fn handle_modules(cli: &Cli) -> Result<()> {
-    let config = load_config(cli)?;
+    let config = load_mount_config(cli)?;
 
     modules::print_list(&config).context("Failed to list modules")
 }
 
 pub fn handle_conflicts(cli: &Cli) -> Result<()> {
-    let config = load_config(cli)?;
+    let config = load_mount_config(cli)?;
 
     let module_list = inventory::scan(&config.moduledir, &config)
         .context("Failed to scan modules for conflict analysis")?;
@@ -152,15 +179,15 @@ pub fn handle_conflicts(cli: &Cli) -> Result<()> {
 }
 
 pub fn handle_diagnostics(cli: &Cli) -> Result<()> {
-    let config = load_config(cli)?;
+    let config = load_mount_config(cli)?;
 
-    let module_list = inventory::scan(&config.moduledir, &config)
+    let scan = inventory::scan_report(&config.moduledir, &config)
         .context("Failed to scan modules for diagnostics")?;
 
-    let plan = planner::generate(&config, &module_list, &config.moduledir)
+    let plan = planner::generate(&config, &scan.modules, &config.moduledir)
         .context("Failed to generate plan for diagnostics")?;
 
-    let issues = executor::diagnose_plan(&plan);
+    let issues = executor::diagnose_plan(&plan, &scan.issues);
 
     let json_issues: Vec<DiagnosticIssueJson> = issues
         .into_iter()
@@ -183,6 +210,283 @@ pub fn handle_diagnostics(cli: &Cli) -> Result<()> {
     Ok(())
 }
 
+pub fn handle_verify(cli: &Cli) -> Result<()> {
+    let config = load_config(cli)?;
+
+    let state = RuntimeState::load().context("Failed to load runtime state")?;
+
+    let report = verify::verify(&state, &config).context("Failed to verify mounts")?;
+
+    let json = serde_json::to_string(&report).context("Failed to serialize verify report")?;
+
+    println!("{}", json);
+
+    Ok(())
+}
+
+pub fn handle_apply(cli: &Cli, module: &str) -> Result<()> {
+    let config = load_mount_config(cli)?;
+
+    let _ = env_logger::builder()
+        .filter_level(if config.verbose || cli.verbose {
+            log::LevelFilter::Debug
+        } else {
+            log::LevelFilter::Info
+        })
+        .try_init();
+
+    graft::apply_module(&config, module)?;
+
+    println!("Module '{}' applied.", module);
+
+    Ok(())
+}
+
+pub fn handle_sync(cli: &Cli, ids: &[String], force: bool) -> Result<()> {
+    let config = load_mount_config(cli)?;
+
+    let _ = env_logger::builder()
+        .filter_level(if config.verbose || cli.verbose {
+            log::LevelFilter::Debug
+        } else {
+            log::LevelFilter::Info
+        })
+        .try_init();
+
+    for id in ids {
+        utils::validate_module_id(id)?;
+    }
+
+    let storage = RuntimeState::load()
+        .context("Failed to load runtime state")?
+        .mount_point;
+
+    let modules = inventory::scan(&config.moduledir, &config)?;
+
+    for id in ids {
+        let current = modules
+            .iter()
+            .find(|m| &m.id == id)
+            .is_some_and(|m| sync::is_current(m, &storage, &config));
+
+        if current && !force {
+            println!("Module '{}' is up to date.", id);
+
+            continue;
+        }
+
+        graft::apply_module(&config, id)?;
+
+        println!("Module '{}' re-synced.", id);
+    }
+
+    Ok(())
+}
+
+pub fn handle_watchdog(cli: &Cli) -> Result<()> {
+    let config = load_config(cli)?;
+
+    let _ = env_logger::builder()
+        .filter_level(if config.verbose || cli.verbose {
+            log::LevelFilter::Debug
+        } else {
+            log::LevelFilter::Info
+        })
+        .try_init();
+
+    granary::watch_boot_completion(&config)
+}
+
+pub fn handle_granary(cli: &Cli, command: &GranaryCommand) -> Result<()> {
+    let config = load_config(cli)?;
+
+    match command {
+        GranaryCommand::Diff { from, to, json } => {
+            let from = silo_diff::resolve(from, &config)?;
+
+            let to = silo_diff::resolve(to, &config)?;
+
+            let diff = silo_diff::diff(&from, &to)?;
+
+            if *json {
+                println!("{}", serde_json::to_string(&diff)?);
+            } else {
+                print!("{}", diff);
+            }
+        }
+        GranaryCommand::Restore {
+            id,
+            winnowing,
+            partitions,
+            rules,
+        } => {
+            let scope = granary::RestoreScope {
+                winnowing: *winnowing,
+                partitions: *partitions,
+                rules: rules.clone(),
+            };
+
+            let undo = granary::restore(&config, id, &scope)?;
+
+            println!("Silo {} restored. Undo with silo {}.", id, undo);
+        }
+        GranaryCommand::Check { json } => {
+            let corrupted = granary::check_silos()?;
+
+            if *json {
+                println!("{}", serde_json::to_string(&corrupted)?);
+            } else if corrupted.is_empty() {
+                println!("All silos are intact.");
+            } else {
+                for silo in &corrupted {
+                    println!("Corrupted: {} ({})", silo.file, silo.error);
+                }
+
+                bail!("{} corrupted silos found", corrupted.len());
+            }
+        }
+        GranaryCommand::Export { id, file } => {
+            let mut silo = silo_diff::resolve(id, &config)?;
+
+            if silo.id == silo_diff::CURRENT {
+                silo.id = granary::new_silo_id()?.0;
+            }
+
+            silo_archive::export(&silo, file)?;
+
+            println!("Silo {} exported to {}", silo.id, file.display());
+        }
+        GranaryCommand::Import { file, remap } => {
+            let remap = remap
+                .iter()
+                .map(|entry| {
+                    entry
+                        .split_once('=')
+                        .map(|(old, new)| (old.trim().to_string(), new.trim().to_string()))
+                        .with_context(|| format!("Invalid remap '{}', expected OLD=NEW", entry))
+                })
+                .collect::<Result<HashMap<_, _>>>()?;
+
+            let id = silo_archive::import(file, &remap)?;
+
+            println!("Silo {} imported.", id);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn handle_profile(cli: &Cli, command: &ProfileCommand) -> Result<()> {
+    let config = load_config(cli)?;
+
+    let config_path = cli
+        .config
+        .clone()
+        .unwrap_or_else(|| CONFIG_FILE_DEFAULT.into());
+
+    let (name, apply) = match command {
+        ProfileCommand::List { json } => {
+            if *json {
+                let list = serde_json::json!({
+                    "active": config.profile,
+                    "profiles": config.profiles,
+                });
+
+                println!("{}", list);
+            } else if config.profiles.is_empty() {
+                println!("No profiles configured.");
+            } else {
+                for (name, p) in &config.profiles {
+                    let marker = if config.profile.as_ref() == Some(name) {
+                        "*"
+                    } else {
+                        " "
+                    };
+
+                    println!(
+                        "{} {} ({} modules, {} rule overrides)",
+                        marker,
+                        name,
+                        p.modules.len(),
+                        p.rules.len()
+                    );
+                }
+            }
+
+            return Ok(());
+        }
+        ProfileCommand::Use { name, apply } => (Some(name.as_str()), *apply),
+        ProfileCommand::Clear { apply } => (None, *apply),
+    };
+
+    let _ = env_logger::builder()
+        .filter_level(if config.verbose || cli.verbose {
+            log::LevelFilter::Debug
+        } else {
+            log::LevelFilter::Info
+        })
+        .try_init();
+
+    let report = profile::switch(&config, name, &config_path, apply)?;
+
+    for id in &report.missing {
+        println!("Not installed: {}", id);
+    }
+
+    println!(
+        "Profile {} active ({} enabled, {} disabled).",
+        name.unwrap_or("(none)"),
+        report.enabled.len(),
+        report.disabled.len()
+    );
+
+    if !apply {
+        println!("Reboot to apply, or rerun with --apply.");
+    } else if report.partitions_changed {
+        println!("Partition changes take effect after a reboot.");
+    }
+
+    Ok(())
+}
+
+pub fn handle_teardown(cli: &Cli) -> Result<()> {
+    let config = load_config(cli)?;
+
+    let report = executor::teardown(&config.mountsource).context("Failed to tear down mounts")?;
+
+    for target in &report.detached {
+        println!("Detached: {}", target.display());
+    }
+
+    for target in &report.stale {
+        println!("Stale, left mounted: {}", target.display());
+    }
+
+    for child in &report.missing_children {
+        println!("Missing child mount: {}", child.display());
+    }
+
+    if !report.failed.is_empty() {
+        for (target, err) in &report.failed {
+            println!("Failed: {} ({})", target.display(), err);
+        }
+
+        bail!(
+            "{} mounts could not be detached; run teardown again",
+            report.failed.len()
+        );
+    }
+
+    println!(
+        "Teardown complete: {} detached, {} already gone, {} stale.",
+        report.detached.len(),
+        report.skipped.len(),
+        report.stale.len()
+    );
+
+    Ok(())
+}
+
 pub fn handle_system_action(cli: &Cli, action: &str, value: Option<&str>) -> Result<()> {
     let mut config = load_config(cli)?;
 
@@ -197,9 +501,9 @@ pub fn handle_system_action(cli: &Cli, action: &str, value: Option<&str>) -> Res
         "granary-create" => {
             let reason = value.unwrap_or("Manual Backup");
 
-            granary::create_silo(&config, "Manual Snapshot", reason)?;
+            let id = granary::create_silo(&config, "Manual Snapshot", reason)?;
 
-            println!("Silo created.");
+            println!("Silo {} saved.", id);
         }
         "granary-delete" => {
             if let Some(id) = value {
@@ -210,15 +514,43 @@ pub fn handle_system_action(cli: &Cli, action: &str, value: Option<&str>) -> Res
                 bail!("Missing Silo ID");
             }
         }
+        "granary-pin" | "granary-unpin" => {
+            if let Some(id) = value {
+                let pinned = action == "granary-pin";
+
+                granary::set_pinned(id, pinned)?;
+
+                println!(
+                    "Silo {} {}.",
+                    id,
+                    if pinned { "pinned" } else { "unpinned" }
+                );
+            } else {
+                bail!("Missing Silo ID");
+            }
+        }
         "granary-restore" => {
             if let Some(id) = value {
-                granary::restore_silo(id)?;
+                let undo = granary::restore(&config, id, &granary::RestoreScope::default())?;
 
-                println!("Silo {} restored. Please reboot.", id);
+                println!("Silo {} restored (undo: {}). Please reboot.", id, undo);
             } else {
                 bail!("Missing Silo ID");
             }
         }
+        "ratoon-status" => {
+            let status = serde_json::json!({
+                "notice": granary::read_rescue_notice(),
+                "bisect": bisect::load(),
+            });
+
+            println!("{}", status);
+        }
+        "ratoon-dismiss" => {
+            granary::dismiss_rescue_notice()?;
+
+            println!("Rescue notice dismissed.");
+        }
         "winnow-set" => {
             if let Some(val) = value
                 && let Some((path, id)) = val.split_once(':')
@@ -2,7 +2,7 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fs,
     path::{Path, PathBuf},
 };
@@ -10,41 +10,44 @@ use std::{
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 
+use crate::core::inventory::ModuleRules;
+
 pub const CONFIG_FILE_DEFAULT: &str = "/data/adb/meta-hybrid/config.toml";
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
-
 pub struct WinnowingTable {
     #[serde(flatten)]
     pub rules: HashMap<String, String>,
 }
 
 impl WinnowingTable {
-    pub fn get_preferred_module(&self, file_path: &Path) -> Option<String> {
-        let path_str = file_path.to_string_lossy().to_string();
-
-        self.rules.get(&path_str).cloned()
-    }
-
     pub fn set_rule(&mut self, file_path: &str, module_id: &str) {
         self.rules
             .insert(file_path.to_string(), module_id.to_string());
     }
 
     #[allow(dead_code)]
-
     pub fn remove_rule(&mut self, file_path: &str) {
         self.rules.remove(file_path);
     }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
-
 pub struct GranaryConfig {
     #[serde(default = "default_max_backups")]
     pub max_backups: usize,
     #[serde(default = "default_retention_days")]
     pub retention_days: u64,
+    /// Consecutive unfinished boots before Ratoon restores a snapshot.
+    #[serde(default = "default_ratoon_threshold")]
+    pub ratoon_threshold: u8,
+    /// Seconds the watchdog waits for `sys.boot_completed`.
+    #[serde(default = "default_boot_timeout")]
+    pub boot_timeout: u64,
+    /// Bisect recently changed modules instead of disabling all of them
+    /// when the Ratoon rollback fails.
+    #[serde(default)]
+    pub bisect: bool,
 }
 
 fn default_max_backups() -> usize {
@@ -55,17 +58,68 @@ fn default_retention_days() -> u64 {
     0
 }
 
+fn default_ratoon_threshold() -> u8 {
+    3
+}
+
+fn default_boot_timeout() -> u64 {
+    300
+}
+
 impl Default for GranaryConfig {
     fn default() -> Self {
         Self {
             max_backups: default_max_backups(),
             retention_days: default_retention_days(),
+            ratoon_threshold: default_ratoon_threshold(),
+            boot_timeout: default_boot_timeout(),
+            bisect: false,
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// What to undo when the mount plan cannot be carried out completely.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RollbackPolicy {
+    /// Keep whatever got mounted.
+    #[default]
+    None,
+    /// Revert every mount of a partition whose content could not be mounted.
+    Partition,
+    /// Revert all mounts of the boot.
+    Global,
+}
 
+/// What to do with a module whose `requires`, `conflicts` or `replaces`
+/// declarations are not satisfied.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DependencyPolicy {
+    /// Leave the module out of the mount plan.
+    #[default]
+    Skip,
+    /// Mount it anyway and report the problem.
+    Warn,
+}
+
+/// A named set of enabled modules, rule overrides and partitions that can
+/// be switched to with `profile use`.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct MountProfile {
+    /// Modules to keep enabled; all others get disabled. Empty leaves the
+    /// module set alone.
+    #[serde(default)]
+    pub modules: Vec<String>,
+    /// Rule overrides layered on top of the user's rule files.
+    #[serde(default)]
+    pub rules: HashMap<String, ModuleRules>,
+    /// Replaces the top-level partition list when not empty.
+    #[serde(default)]
+    pub partitions: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
     #[serde(default = "default_moduledir")]
     pub moduledir: PathBuf,
@@ -90,6 +144,21 @@ pub struct Config {
     pub winnowing: WinnowingTable,
     #[serde(default)]
     pub granary: GranaryConfig,
+    #[serde(default)]
+    pub priorities: HashMap<String, i32>,
+    #[serde(default)]
+    pub checksum_sync: bool,
+    #[serde(default)]
+    pub rollback_policy: RollbackPolicy,
+    #[serde(default)]
+    pub dependency_policy: DependencyPolicy,
+    /// Name of the active entry of `profiles`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, MountProfile>,
+    #[serde(skip)]
+    pub force_resync: Vec<String>,
 }
 
 fn default_moduledir() -> PathBuf {
@@ -106,7 +175,6 @@ where
 {
     #[derive(Deserialize)]
     #[serde(untagged)]
-
     enum StringOrVec {
         String(String),
         Vec(Vec<String>),
@@ -137,6 +205,13 @@ impl Default for Config {
             dry_run: false,
             winnowing: WinnowingTable::default(),
             granary: GranaryConfig::default(),
+            priorities: HashMap::new(),
+            checksum_sync: false,
+            rollback_policy: RollbackPolicy::default(),
+            dependency_policy: DependencyPolicy::default(),
+            profile: None,
+            profiles: BTreeMap::new(),
+            force_resync: Vec::new(),
         }
     }
 }
@@ -166,6 +241,20 @@ impl Config {
         Ok(())
     }
 
+    pub fn active_profile(&self) -> Option<&MountProfile> {
+        self.profiles.get(self.profile.as_ref()?)
+    }
+
+    /// Puts the active profile's partition list in effect. Only meant for
+    /// configs used to mount, never for ones written back to disk.
+    pub fn apply_profile(&mut self) {
+        if let Some(profile) = self.active_profile()
+            && !profile.partitions.is_empty()
+        {
+            self.partitions = profile.partitions.clone();
+        }
+    }
+
     pub fn merge_with_cli(
         &mut self,
         moduledir: Option<PathBuf>,
@@ -0,0 +1,271 @@
+// Copyright 2025 Meta-Hybrid Mount Authors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{fmt, fs, path::Path};
+
+use anyhow::{Context, Result, bail};
+use serde::Serialize;
+
+use crate::utils;
+
+/// A top-level key or table and the lines it spans.
+struct Item {
+    name: String,
+    start: usize,
+    end: usize,
+    table: bool,
+}
+
+/// A `config.toml` edited in place. Only keys passed to [`ConfigFile::set`]
+/// or [`ConfigFile::remove`] are rewritten; comments, key order and layout
+/// of everything else are kept byte for byte.
+#[derive(Debug, Clone)]
+pub struct ConfigFile {
+    /// Split on `\n` only, so a trailing newline shows up as a last empty
+    /// line and `\r` stays where it was.
+    lines: Vec<String>,
+}
+
+fn parse(lines: &[String]) -> Option<toml::Table> {
+    let text = lines
+        .iter()
+        .map(|l| l.strip_suffix('\r').unwrap_or(l))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    toml::from_str(&text).ok()
+}
+
+fn first_key(table: &toml::Table) -> Option<String> {
+    table.keys().next().cloned()
+}
+
+impl ConfigFile {
+    pub fn parse(content: &str) -> Self {
+        Self {
+            lines: content.split('\n').map(String::from).collect(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+
+        Ok(Self::parse(&content))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let content = self.to_string();
+
+        if let Err(e) = toml::from_str::<toml::Table>(&content) {
+            bail!("Refusing to write invalid config: {}", e);
+        }
+
+        utils::atomic_write(path, content.as_bytes())
+    }
+
+    /// Top-level items in file order. A table's span runs from its header
+    /// to its last key, so comments above the next header stay with it.
+    fn items(&self) -> Vec<Item> {
+        let mut items: Vec<Item> = Vec::new();
+
+        let mut in_root = true;
+
+        let mut i = 0;
+
+        while i < self.lines.len() {
+            let trimmed = self.lines[i].trim();
+
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                i += 1;
+
+                continue;
+            }
+
+            if trimmed.starts_with('[') {
+                in_root = false;
+
+                let name = parse(&self.lines[i..=i])
+                    .as_ref()
+                    .and_then(first_key)
+                    .unwrap_or_default();
+
+                match items.last_mut() {
+                    Some(last) if last.table && last.name == name => last.end = i + 1,
+                    _ => items.push(Item {
+                        name,
+                        start: i,
+                        end: i + 1,
+                        table: true,
+                    }),
+                }
+
+                i += 1;
+
+                continue;
+            }
+
+            // A value may span lines; it ends where the lines parse.
+            let (end, name) = (i + 1..=self.lines.len())
+                .find_map(|end| {
+                    parse(&self.lines[i..end]).map(|t| (end, first_key(&t).unwrap_or_default()))
+                })
+                .unwrap_or((i + 1, String::new()));
+
+            if in_root {
+                items.push(Item {
+                    name,
+                    start: i,
+                    end,
+                    table: false,
+                });
+            } else if let Some(last) = items.last_mut() {
+                last.end = end;
+            }
+
+            i = end;
+        }
+
+        items
+    }
+
+    /// Drops every line of `key`. Returns where it was first found.
+    fn take(&mut self, key: &str) -> Option<usize> {
+        let spans: Vec<(usize, usize)> = self
+            .items()
+            .into_iter()
+            .filter(|item| item.name == key)
+            .map(|item| (item.start, item.end))
+            .collect();
+
+        for (start, end) in spans.iter().rev() {
+            self.lines.drain(start..end);
+        }
+
+        spans.first().map(|(start, _)| *start)
+    }
+
+    /// Rewrites the top-level key or table `key` in place, or adds it: keys
+    /// after the last top-level key, tables at the end.
+    pub fn set<T: Serialize + ?Sized>(&mut self, key: &str, value: &T) -> Result<()> {
+        let value = toml::Value::try_from(value)
+            .with_context(|| format!("Failed to serialize config key {}", key))?;
+
+        let table = value.is_table();
+
+        let mut doc = toml::Table::new();
+
+        doc.insert(key.to_string(), value);
+
+        let rendered = toml::to_string(&doc)?;
+
+        let new_lines = rendered
+            .trim_end_matches('\n')
+            .split('\n')
+            .map(String::from);
+
+        let at = match self.take(key) {
+            Some(at) => at,
+            None => {
+                let items = self.items();
+
+                // Keep the final empty line that stands for the trailing newline.
+                let end =
+                    self.lines.len() - usize::from(self.lines.last().is_some_and(String::is_empty));
+
+                if table {
+                    if end > 0 && !self.lines[end - 1].trim().is_empty() {
+                        self.lines.insert(end, String::new());
+
+                        end + 1
+                    } else {
+                        end
+                    }
+                } else {
+                    items
+                        .iter()
+                        .rfind(|item| !item.table)
+                        .map_or(0, |item| item.end)
+                }
+            }
+        };
+
+        self.lines.splice(at..at, new_lines);
+
+        if self.lines.last().is_none_or(|l| !l.is_empty()) {
+            self.lines.push(String::new());
+        }
+
+        Ok(())
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.take(key);
+    }
+}
+
+impl fmt::Display for ConfigFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.lines.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    const SAMPLE: &str = "# Meta-Hybrid config\nmoduledir = \"/data/adb/modules\"\npartitions = [\n    \"odm\", # vendor blobs\n    \"my_product\",\n]\nverbose = true\n\n# Conflict winners\n[winnowing]\n\"/system/a\" = \"m1\"\n\n# Backups\n[granary]\nmax_backups = 20\n";
+
+    #[test]
+    fn round_trip_is_exact() {
+        for content in [SAMPLE, "", "a = 1", "a = 1\r\n", "\n\n"] {
+            assert_eq!(ConfigFile::parse(content).to_string(), content);
+        }
+    }
+
+    #[test]
+    fn set_replaces_multiline_value_only() {
+        let mut file = ConfigFile::parse(SAMPLE);
+
+        file.set("partitions", &["odm"]).unwrap();
+
+        assert_eq!(
+            file.to_string(),
+            SAMPLE.replace(
+                "partitions = [\n    \"odm\", # vendor blobs\n    \"my_product\",\n]",
+                "partitions = [\"odm\"]"
+            )
+        );
+    }
+
+    #[test]
+    fn set_replaces_table_and_keeps_neighbouring_comments() {
+        let mut file = ConfigFile::parse(SAMPLE);
+
+        file.set("winnowing", &BTreeMap::from([("/vendor/b", "m2")]))
+            .unwrap();
+
+        assert_eq!(
+            file.to_string(),
+            SAMPLE.replace("\"/system/a\" = \"m1\"", "\"/vendor/b\" = \"m2\"")
+        );
+    }
+
+    #[test]
+    fn new_keys_go_after_top_level_keys() {
+        let mut file = ConfigFile::parse(SAMPLE);
+
+        file.set("profile", "daily").unwrap();
+
+        assert_eq!(
+            file.to_string(),
+            SAMPLE.replace("verbose = true\n", "verbose = true\nprofile = \"daily\"\n")
+        );
+
+        file.remove("profile");
+
+        assert_eq!(file.to_string(), SAMPLE);
+    }
+}
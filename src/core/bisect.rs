@@ -0,0 +1,282 @@
+// Copyright 2025 Meta-Hybrid Mount Authors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{core::granary, defs};
+
+const BISECT_STATE_FILE: &str = "/data/adb/meta-hybrid/ratoon_bisect.json";
+
+/// Progress of a Ratoon bisection, kept across reboots.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct BisectState {
+    pub round: u32,
+    /// Every module taking part, re-enabled once the bisection ends.
+    pub modules: Vec<String>,
+    /// Modules that may still be the culprit.
+    pub suspects: Vec<String>,
+    /// Suspects enabled in the current round; the rest stay disabled.
+    pub testing: Vec<String>,
+    /// Set once a boot was attempted with the current round.
+    #[serde(default)]
+    pub booted: bool,
+}
+
+impl BisectState {
+    fn save(&self) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+
+        fs::write(BISECT_STATE_FILE, json).context("Failed to write bisect state")
+    }
+
+    /// Starts a round testing the first half of the suspects.
+    fn split(&mut self) {
+        self.round += 1;
+
+        self.testing = self.suspects[..self.suspects.len() / 2].to_vec();
+    }
+
+    /// The culprit is among the modules of a round that failed to boot.
+    fn round_failed(&mut self) {
+        self.suspects = std::mem::take(&mut self.testing);
+    }
+
+    /// Clears the modules of a round that booted; returns them.
+    fn round_passed(&mut self) -> Vec<String> {
+        let cleared = std::mem::take(&mut self.testing);
+
+        self.suspects.retain(|id| !cleared.contains(id));
+
+        cleared
+    }
+
+    fn next_round(&mut self, moduledir: &Path) -> Result<()> {
+        self.split();
+
+        for id in &self.suspects {
+            let enabled = self.testing.contains(id);
+
+            granary::set_flag(&moduledir.join(id), defs::DISABLE_FILE_NAME, !enabled)?;
+        }
+
+        log::warn!(
+            ">> Ratoon Bisect round {}: testing {:?} ({} suspects left)",
+            self.round,
+            self.testing,
+            self.suspects.len()
+        );
+
+        Ok(())
+    }
+
+    /// Re-enables every module except the culprit and ends the bisection.
+    fn finish(&self, moduledir: &Path, culprit: Option<&str>) -> Result<()> {
+        for id in &self.modules {
+            let disabled = Some(id.as_str()) == culprit;
+
+            granary::set_flag(&moduledir.join(id), defs::DISABLE_FILE_NAME, disabled)?;
+        }
+
+        let notice = match culprit {
+            Some(id) => format!(
+                "Bootloop traced to module '{}' after {} bisect rounds. It has been disabled; other modules were re-enabled.",
+                id, self.round
+            ),
+            None => format!(
+                "Bisect finished after {} rounds without a failing module. All suspects were re-enabled.",
+                self.round
+            ),
+        };
+
+        log::warn!(">> Ratoon Bisect: {}", notice);
+
+        granary::write_rescue_notice(&notice);
+
+        let _ = fs::remove_file(BISECT_STATE_FILE);
+
+        Ok(())
+    }
+}
+
+pub fn load() -> Option<BisectState> {
+    let content = fs::read_to_string(BISECT_STATE_FILE).ok()?;
+
+    serde_json::from_str(&content).ok()
+}
+
+/// Starts bisecting `suspects`, applying the first round to the current boot.
+pub fn start(moduledir: &Path, suspects: Vec<String>) -> Result<()> {
+    let mut state = BisectState {
+        modules: suspects.clone(),
+        suspects,
+        ..Default::default()
+    };
+
+    match state.suspects.len() {
+        0 => {
+            granary::write_rescue_notice("Bootloop detected but no module is enabled to bisect.");
+
+            Ok(())
+        }
+        1 => {
+            let culprit = state.suspects[0].clone();
+
+            state.finish(moduledir, Some(&culprit))
+        }
+        _ => {
+            state.next_round(moduledir)?;
+
+            state.booted = true;
+
+            state.save()
+        }
+    }
+}
+
+/// Called early in every boot while a bisection runs. A round that was
+/// already booted without reaching boot completion counts as failed.
+pub fn on_boot(mut state: BisectState, moduledir: &Path) -> Result<()> {
+    if !state.booted {
+        state.booted = true;
+
+        return state.save();
+    }
+
+    log::warn!(">> Ratoon Bisect round {} failed.", state.round);
+
+    state.round_failed();
+
+    if state.suspects.len() == 1 {
+        let culprit = state.suspects[0].clone();
+
+        return state.finish(moduledir, Some(&culprit));
+    }
+
+    state.next_round(moduledir)?;
+
+    state.save()
+}
+
+/// Called once Android reports boot completion. The modules of the current
+/// round are cleared and the next round is prepared for the following boot.
+pub fn on_boot_completed(moduledir: &Path) -> Result<()> {
+    let Some(mut state) = load() else {
+        return Ok(());
+    };
+
+    log::info!(">> Ratoon Bisect round {} passed.", state.round);
+
+    let cleared = state.round_passed();
+
+    for id in &cleared {
+        granary::set_flag(&moduledir.join(id), defs::DISABLE_FILE_NAME, false)?;
+    }
+
+    match state.suspects.len() {
+        0 => state.finish(moduledir, None),
+        1 => {
+            let culprit = state.suspects[0].clone();
+
+            state.finish(moduledir, Some(&culprit))
+        }
+        _ => {
+            state.next_round(moduledir)?;
+
+            state.booted = false;
+
+            granary::write_rescue_notice(&format!(
+                "Bootloop bisect in progress: round {} passed, reboot to continue ({} suspects left).",
+                state.round - 1,
+                state.suspects.len()
+            ));
+
+            state.save()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(ids: &[&str]) -> BisectState {
+        let modules: Vec<String> = ids.iter().map(|s| s.to_string()).collect();
+
+        BisectState {
+            suspects: modules.clone(),
+            modules,
+            ..Default::default()
+        }
+    }
+
+    /// Runs rounds until one suspect is left, booting fails whenever
+    /// `culprit` is enabled.
+    fn run(ids: &[&str], culprit: &str) -> BisectState {
+        let mut state = state(ids);
+
+        while state.suspects.len() > 1 {
+            state.split();
+
+            if state.testing.iter().any(|id| id == culprit) {
+                state.round_failed();
+            } else {
+                state.round_passed();
+            }
+        }
+
+        state
+    }
+
+    #[test]
+    fn split_tests_the_first_half() {
+        let mut state = state(&["a", "b", "c", "d", "e"]);
+
+        state.split();
+
+        assert_eq!(state.round, 1);
+
+        assert_eq!(state.testing, ["a", "b"]);
+    }
+
+    #[test]
+    fn failed_round_narrows_to_the_tested_half() {
+        let mut state = state(&["a", "b", "c", "d"]);
+
+        state.split();
+
+        state.round_failed();
+
+        assert_eq!(state.suspects, ["a", "b"]);
+
+        assert!(state.testing.is_empty());
+    }
+
+    #[test]
+    fn passed_round_clears_the_tested_half() {
+        let mut state = state(&["a", "b", "c", "d"]);
+
+        state.split();
+
+        assert_eq!(state.round_passed(), ["a", "b"]);
+
+        assert_eq!(state.suspects, ["c", "d"]);
+    }
+
+    #[test]
+    fn finds_any_culprit_in_logarithmic_rounds() {
+        let ids = ["a", "b", "c", "d", "e", "f", "g"];
+
+        for culprit in ids {
+            let state = run(&ids, culprit);
+
+            assert_eq!(state.suspects, [culprit]);
+
+            assert!(state.round <= 3, "{} took {} rounds", culprit, state.round);
+
+            assert_eq!(state.modules.len(), ids.len());
+        }
+    }
+}
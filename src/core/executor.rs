@@ -7,22 +7,65 @@ use std::{
     process::Command,
 };
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use procfs::process::Process;
 use rayon::prelude::*;
 use rustix::mount::UnmountFlags;
+use serde::{Deserialize, Serialize};
 use walkdir::WalkDir;
 
 use crate::{
-    conf::config,
-    core::planner::MountPlan,
+    conf::config::{self, RollbackPolicy},
+    core::{
+        inventory,
+        planner::{MountPlan, OverlayOperation},
+        state::RuntimeState,
+        verify::{self, Holder},
+    },
     defs,
-    mount::{magic, overlay},
+    mount::{
+        journal::{self, MountRecord, MountRole},
+        magic::{self, MagicScope},
+        overlay,
+    },
     utils,
 };
 
 pub struct ExecutionResult {
     pub overlay_module_ids: Vec<String>,
     pub magic_module_ids: Vec<String>,
+    pub outcome: ExecutionOutcome,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecutionStatus {
+    #[default]
+    Complete,
+    /// Part of the plan failed and the mounts that did succeed were kept.
+    Degraded,
+    /// Part of the plan failed and mounts were reverted per the policy.
+    RolledBack,
+}
+
+/// How the mount transaction of a boot ended.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExecutionOutcome {
+    pub policy: RollbackPolicy,
+    pub status: ExecutionStatus,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reverted_partitions: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Default)]
+pub struct TeardownReport {
+    pub detached: Vec<PathBuf>,
+    pub skipped: Vec<PathBuf>,
+    pub stale: Vec<PathBuf>,
+    pub failed: Vec<(PathBuf, String)>,
+    pub missing_children: Vec<PathBuf>,
 }
 
 pub enum DiagnosticLevel {
@@ -48,10 +91,65 @@ fn extract_module_root(partition_path: &Path) -> Option<PathBuf> {
     partition_path.parent().map(|p| p.to_path_buf())
 }
 
+/// Partitions magic mount was going to touch for `roots`, including those
+/// it already reached before failing.
+fn magic_partitions(roots: &[PathBuf], mark: u64, config: &config::Config) -> HashSet<String> {
+    let mut partitions: HashSet<String> = journal::active_since(mark)
+        .iter()
+        .filter(|r| r.role == MountRole::Magic)
+        .map(|r| r.partition.clone())
+        .collect();
+
+    for root in roots {
+        let Ok(entries) = std::fs::read_dir(root) else {
+            continue;
+        };
+
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+
+            let known = defs::BUILTIN_PARTITIONS.contains(&name.as_str())
+                || config.partitions.contains(&name);
+
+            if known && entry.path().is_dir() {
+                partitions.insert(name);
+            }
+        }
+    }
+
+    partitions
+}
+
+/// Detaches the mounts recorded since `mark`, newest first, limited to
+/// `scope` when given. Returns the partitions that lost mounts.
+fn revert_since(mark: u64, scope: Option<&HashSet<String>>, mount_source: &str) -> Vec<String> {
+    let mut reverted = Vec::new();
+
+    for record in journal::active_since(mark).iter().rev() {
+        let partition = &record.partition;
+
+        if scope.is_some_and(|s| !s.contains(partition)) {
+            continue;
+        }
+
+        if let Err(e) = detach(record, mount_source) {
+            log::error!("Rollback: {:#}", e);
+        }
+
+        if !reverted.contains(partition) {
+            reverted.push(partition.clone());
+        }
+    }
+
+    reverted.sort();
+
+    reverted
+}
+
 struct OverlayResult {
+    partition: String,
     magic_roots: Vec<PathBuf>,
     fallback_ids: Vec<String>,
-    success_records: Vec<(PathBuf, String)>,
 }
 
 fn repair_rw_contexts() {
@@ -103,9 +201,21 @@ fn repair_rw_contexts() {
     }
 }
 
-pub fn diagnose_plan(plan: &MountPlan) -> Vec<DiagnosticIssue> {
+pub fn diagnose_plan(plan: &MountPlan, skipped: &[inventory::ModuleIssue]) -> Vec<DiagnosticIssue> {
     let mut issues = Vec::new();
 
+    for skip in skipped {
+        issues.push(DiagnosticIssue {
+            level: DiagnosticLevel::Warning,
+            context: skip.id.clone(),
+            message: if skip.dropped {
+                format!("Module dropped from the plan: {}", skip.reason)
+            } else {
+                format!("Module kept in the plan despite: {}", skip.reason)
+            },
+        });
+    }
+
     for op in &plan.overlay_ops {
         let target = Path::new(&op.target);
 
@@ -157,10 +267,151 @@ pub fn diagnose_plan(plan: &MountPlan) -> Vec<DiagnosticIssue> {
     issues
 }
 
+/// Mounts one planned partition overlay, picking up the partition's RW
+/// upper and work dirs when both exist.
+pub fn mount_overlay_op(op: &OverlayOperation, disable_umount: bool) -> Result<()> {
+    let lowerdir_strings: Vec<String> = op
+        .lowerdirs
+        .iter()
+        .map(|p: &PathBuf| p.display().to_string())
+        .collect();
+
+    let rw_root = Path::new(defs::SYSTEM_RW_DIR);
+
+    let part_rw = rw_root.join(&op.partition_name);
+
+    let upper = part_rw.join("upperdir");
+
+    let work = part_rw.join("workdir");
+
+    let (upper_opt, work_opt) = if upper.exists() && work.exists() {
+        (Some(upper), Some(work))
+    } else {
+        (None, None)
+    };
+
+    log::info!(
+        "Mounting {} [OVERLAY] (Layers: {})",
+        op.target,
+        lowerdir_strings.len()
+    );
+
+    overlay::mount_overlay(
+        &op.target,
+        &lowerdir_strings,
+        work_opt,
+        upper_opt,
+        !op.umount.should_register(!disable_umount),
+    )
+}
+
+/// What [`detach`] did with a journaled mount.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Detached {
+    Done,
+    /// The mount was already gone.
+    Gone,
+    /// The mount is still there but covered by a foreign one, so it was left
+    /// alone and stays active.
+    Covered,
+    /// A foreign mount took its place; nothing was unmounted.
+    Replaced,
+}
+
+/// Detaches a journaled mount and marks it reverted. Only the topmost mount
+/// at the target is detached, and only if it is the recorded one, so a stale
+/// record never takes the stock mount below it along.
+pub fn detach(record: &MountRecord, mount_source: &str) -> Result<Detached> {
+    let infos: Vec<_> = Process::myself()
+        .and_then(|p| p.mountinfo())
+        .context("Failed to read mountinfo")?
+        .into_iter()
+        .collect();
+
+    match verify::holder(record, &infos, mount_source) {
+        Holder::Record => {}
+        Holder::Nobody => {
+            journal::revert(&record.target);
+
+            return Ok(Detached::Gone);
+        }
+        Holder::Covered => {
+            log::warn!(
+                "Not detaching {}: covered by a foreign mount",
+                record.target.display()
+            );
+
+            return Ok(Detached::Covered);
+        }
+        Holder::Other => {
+            log::warn!(
+                "Not detaching {}: no longer our {} mount",
+                record.target.display(),
+                record.fs_type
+            );
+
+            journal::revert(&record.target);
+
+            return Ok(Detached::Replaced);
+        }
+    }
+
+    log::info!(
+        "Detaching {} [{}] ({})",
+        record.target.display(),
+        record.fs_type,
+        record.source
+    );
+
+    rustix::mount::unmount(&record.target, UnmountFlags::DETACH)
+        .with_context(|| format!("Failed to detach {}", record.target.display()))?;
+
+    journal::revert(&record.target);
+
+    Ok(Detached::Done)
+}
+
+/// Magic mounts `roots` using a private tmpfs work area.
+pub fn mount_magic(
+    roots: &[PathBuf],
+    scopes: &HashMap<PathBuf, MagicScope>,
+    config: &config::Config,
+) -> Result<()> {
+    let tempdir = utils::select_temp_dir()?;
+
+    log::debug!("Magic Mount work area: {}", tempdir.display());
+
+    if !tempdir.exists() {
+        std::fs::create_dir_all(&tempdir)?;
+    }
+
+    utils::mount_tmpfs(&tempdir, "tmpfs")?;
+
+    let result = magic::mount_partitions(
+        &tempdir,
+        roots,
+        &config.mountsource,
+        &config.partitions,
+        scopes,
+        config.disable_umount,
+    );
+
+    let _ = rustix::mount::unmount(&tempdir, UnmountFlags::DETACH);
+
+    result
+}
+
 pub fn execute(plan: &MountPlan, config: &config::Config) -> Result<ExecutionResult> {
+    let mark = journal::next_order();
+
+    let mut outcome = ExecutionOutcome {
+        policy: config.rollback_policy,
+        ..Default::default()
+    };
+
     let mut magic_queue = plan.magic_module_paths.clone();
 
-    let mut global_success_map: HashMap<PathBuf, HashSet<String>> = HashMap::new();
+    let mut magic_scopes = plan.magic_scopes.clone();
 
     let mut final_overlay_ids = HashSet::new();
 
@@ -176,39 +427,7 @@ pub fn execute(plan: &MountPlan, config: &config::Config) -> Result<ExecutionRes
         .overlay_ops
         .par_iter()
         .map(|op| {
-            let lowerdir_strings: Vec<String> = op
-                .lowerdirs
-                .iter()
-                .map(|p: &PathBuf| p.display().to_string())
-                .collect();
-
-            let rw_root = Path::new(defs::SYSTEM_RW_DIR);
-
-            let part_rw = rw_root.join(&op.partition_name);
-
-            let upper = part_rw.join("upperdir");
-
-            let work = part_rw.join("workdir");
-
-            let (upper_opt, work_opt) = if upper.exists() && work.exists() {
-                (Some(upper), Some(work))
-            } else {
-                (None, None)
-            };
-
-            log::info!(
-                "Mounting {} [OVERLAY] (Layers: {})",
-                op.target,
-                lowerdir_strings.len()
-            );
-
-            if let Err(e) = overlay::mount_overlay(
-                &op.target,
-                &lowerdir_strings,
-                work_opt,
-                upper_opt,
-                config.disable_umount,
-            ) {
+            if let Err(e) = mount_overlay_op(op, config.disable_umount) {
                 log::warn!(
                     "OverlayFS failed for {}: {}. Triggering fallback.",
                     op.target,
@@ -229,84 +448,113 @@ pub fn execute(plan: &MountPlan, config: &config::Config) -> Result<ExecutionRes
                     }
                 }
 
+                journal::record(
+                    MountRecord::new(
+                        Path::new(&op.target),
+                        "overlay",
+                        defs::KSU_OVERLAY_SOURCE,
+                        MountRole::Overlay,
+                    )
+                    .lowerdirs(&op.lowerdirs)
+                    .failed(&e)
+                    .fallback(format!(
+                        "magic mount takes over {} for {}",
+                        op.partition_name,
+                        local_fallback_ids.join(", ")
+                    )),
+                );
+
                 return OverlayResult {
+                    partition: op.partition_name.clone(),
                     magic_roots: local_magic,
                     fallback_ids: local_fallback_ids,
-                    success_records: Vec::new(),
                 };
             }
 
-            let mut successes = Vec::new();
-
-            for layer_path in &op.lowerdirs {
-                if let Some(root) = extract_module_root(layer_path) {
-                    successes.push((root, op.partition_name.clone()));
-                }
-            }
-
             OverlayResult {
+                partition: op.partition_name.clone(),
                 magic_roots: Vec::new(),
                 fallback_ids: Vec::new(),
-                success_records: successes,
             }
         })
         .collect();
 
     for res in overlay_results {
+        for root in &res.magic_roots {
+            magic_scopes
+                .entry(root.clone())
+                .or_default()
+                .partitions
+                .insert(res.partition.clone());
+        }
+
         magic_queue.extend(res.magic_roots);
 
         for id in res.fallback_ids {
             final_overlay_ids.remove(&id);
         }
-
-        for (root, partition) in res.success_records {
-            global_success_map
-                .entry(root)
-                .or_default()
-                .insert(partition);
-        }
     }
 
-    magic_queue.sort();
+    let rank = |path: &PathBuf| {
+        path.file_name()
+            .and_then(|name| plan.precedence.iter().position(|id| name == id.as_str()))
+            .unwrap_or(usize::MAX)
+    };
+
+    magic_queue.sort_by(|a, b| rank(a).cmp(&rank(b)).then_with(|| a.cmp(b)));
 
     magic_queue.dedup();
 
     let mut final_magic_ids = Vec::new();
 
     if !magic_queue.is_empty() {
-        let tempdir = utils::select_temp_dir()?;
-
         for path in &magic_queue {
             if let Some(name) = path.file_name() {
                 final_magic_ids.push(name.to_string_lossy().to_string());
             }
         }
 
-        log::info!(
-            ">> Phase 3: Magic Mount (Fallback) using {}",
-            tempdir.display()
-        );
-
-        if !tempdir.exists() {
-            std::fs::create_dir_all(&tempdir)?;
-        }
+        log::info!(">> Phase 3: Magic Mount (Fallback)...");
 
-        utils::mount_tmpfs(&tempdir, "tmpfs")?;
+        let magic_mark = journal::next_order();
 
-        if let Err(e) = magic::mount_partitions(
-            &tempdir,
-            &magic_queue,
-            &config.mountsource,
-            &config.partitions,
-            global_success_map,
-            config.disable_umount,
-        ) {
+        if let Err(e) = mount_magic(&magic_queue, &magic_scopes, config) {
             log::error!("Magic Mount critical failure: {:#}", e);
 
             final_magic_ids.clear();
+
+            outcome.error = Some(format!("{:#}", e));
+
+            outcome.status = match config.rollback_policy {
+                RollbackPolicy::None => ExecutionStatus::Degraded,
+                policy => {
+                    let scope = (policy == RollbackPolicy::Partition)
+                        .then(|| magic_partitions(&magic_queue, magic_mark, config));
+
+                    log::warn!(">> Rolling back mounts ({:?} policy)...", policy);
+
+                    outcome.reverted_partitions =
+                        revert_since(mark, scope.as_ref(), &config.mountsource);
+
+                    ExecutionStatus::RolledBack
+                }
+            };
         }
+    }
 
-        let _ = rustix::mount::unmount(&tempdir, UnmountFlags::DETACH);
+    if !outcome.reverted_partitions.is_empty() {
+        let kept: HashSet<String> = plan
+            .overlay_ops
+            .iter()
+            .filter(|op| {
+                !outcome
+                    .reverted_partitions
+                    .contains(&journal::partition_of(Path::new(&op.target)))
+            })
+            .flat_map(|op| op.lowerdirs.iter().filter_map(|l| extract_id(l)))
+            .collect();
+
+        final_overlay_ids.retain(|id| kept.contains(id));
     }
 
     let mut result_overlay = final_overlay_ids.into_iter().collect::<Vec<_>>();
@@ -322,5 +570,79 @@ pub fn execute(plan: &MountPlan, config: &config::Config) -> Result<ExecutionRes
     Ok(ExecutionResult {
         overlay_module_ids: result_overlay,
         magic_module_ids: result_magic,
+        outcome,
     })
 }
+
+/// Detaches every mount recorded in the journal, newest first.
+///
+/// Child mounts stashed before an overlay went up were cloned, so the
+/// originals reappear once the overlay covering them is detached; they are
+/// only checked here. Records that could not be detached, or whose mount is
+/// covered by a foreign one, are kept in the journal so the teardown can be
+/// retried.
+pub fn teardown(mount_source: &str) -> Result<TeardownReport> {
+    let records = journal::load()?;
+
+    let mut report = TeardownReport::default();
+
+    let mut remaining = Vec::new();
+
+    for record in records.iter().rev().filter(|r| r.is_active()) {
+        match detach(record, mount_source) {
+            Ok(Detached::Done) => report.detached.push(record.target.clone()),
+            Ok(Detached::Gone) => {
+                report.skipped.push(record.target.clone());
+
+                continue;
+            }
+            Ok(Detached::Covered) => {
+                report.stale.push(record.target.clone());
+
+                remaining.push(record.clone());
+
+                continue;
+            }
+            Ok(Detached::Replaced) => {
+                report.stale.push(record.target.clone());
+
+                continue;
+            }
+            Err(e) => {
+                log::warn!("{:#}", e);
+
+                report
+                    .failed
+                    .push((record.target.clone(), format!("{:#}", e)));
+
+                remaining.push(record.clone());
+
+                continue;
+            }
+        }
+
+        for child in &record.stashed_children {
+            if !utils::is_mounted(child) {
+                log::warn!(
+                    "Stashed child mount {} did not reappear after detaching {}",
+                    child.display(),
+                    record.target.display()
+                );
+
+                report.missing_children.push(child.clone());
+            }
+        }
+    }
+
+    if remaining.is_empty() {
+        journal::clear()?;
+
+        RuntimeState::clear()?;
+    } else {
+        remaining.reverse();
+
+        journal::save(&remaining)?;
+    }
+
+    Ok(report)
+}
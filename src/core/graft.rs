@@ -0,0 +1,358 @@
+// Copyright 2025 Meta-Hybrid Mount Authors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+
+use crate::{
+    conf::config::Config,
+    core::{
+        executor,
+        inventory::{self, UmountPolicy},
+        planner::{self, OverlayOperation},
+        state::RuntimeState,
+        sync,
+    },
+    mount::{
+        journal::{self, MountRecord, MountRole},
+        magic::MagicScope,
+    },
+    try_umount, utils,
+};
+
+/// Everything needed to put the previous stack of one module back.
+struct Rollback {
+    mark: u64,
+    live: PathBuf,
+    backup: PathBuf,
+    overlays: Vec<(u64, OverlayOperation)>,
+    magic_orders: Vec<u64>,
+    magic_roots: Vec<PathBuf>,
+}
+
+fn was_detached(order: u64) -> bool {
+    journal::entries()
+        .iter()
+        .any(|r| r.order == order && !r.is_active())
+}
+
+fn touches(record: &MountRecord, id: &str, roots: &[&Path]) -> bool {
+    record.module_ids(roots).iter().any(|m| m == id)
+}
+
+fn restore_content(live: &Path, backup: &Path) -> Result<()> {
+    if !backup.exists() {
+        return Ok(());
+    }
+
+    if live.exists() {
+        fs::remove_dir_all(live)
+            .with_context(|| format!("Failed to drop new content {}", live.display()))?;
+    }
+
+    fs::rename(backup, live)
+        .with_context(|| format!("Failed to restore content {}", live.display()))
+}
+
+fn roll_back(
+    rollback: &Rollback,
+    scopes: &HashMap<PathBuf, MagicScope>,
+    config: &Config,
+) -> Result<()> {
+    for record in journal::active_since(rollback.mark).iter().rev() {
+        executor::detach(record, &config.mountsource)?;
+    }
+
+    restore_content(&rollback.live, &rollback.backup)?;
+
+    for (order, op) in &rollback.overlays {
+        if was_detached(*order) {
+            executor::mount_overlay_op(op, config.disable_umount)
+                .with_context(|| format!("Failed to remount previous overlay on {}", op.target))?;
+        }
+    }
+
+    if !rollback.magic_roots.is_empty() && rollback.magic_orders.iter().any(|o| was_detached(*o)) {
+        executor::mount_magic(&rollback.magic_roots, scopes, config)
+            .context("Failed to remount previous magic mounts")?;
+    }
+
+    Ok(())
+}
+
+/// Syncs a single module into the live storage and remounts only the
+/// partitions it contributes to, without a reboot. A module that is no
+/// longer enabled is taken out of the stack instead.
+///
+/// Partitions whose overlay also carries magic mounts of other modules are
+/// refused, since detaching the overlay would take those mounts down too.
+/// On failure the previous content and mounts are put back.
+pub fn apply_module(config: &Config, id: &str) -> Result<()> {
+    utils::validate_module_id(id)?;
+
+    let mut state = RuntimeState::load().context("Failed to load runtime state")?;
+
+    let storage = state.mount_point.clone();
+
+    if state.mounts.is_empty() || !utils::is_mounted(&storage) {
+        bail!("No active mount session found; boot the daemon first");
+    }
+
+    if state.storage_mode == "erofs" {
+        bail!(
+            "EROFS storage is read-only; a reboot is required to apply {}",
+            id
+        );
+    }
+
+    journal::resume(state.mounts.clone());
+
+    let roots = [storage.as_path(), config.moduledir.as_path()];
+
+    let modules = inventory::scan(&config.moduledir, config)?;
+
+    let module = modules.iter().find(|m| m.id == id);
+
+    let active: Vec<MountRecord> = state
+        .mounts
+        .iter()
+        .filter(|r| r.is_active())
+        .cloned()
+        .collect();
+
+    let old_overlays: Vec<&MountRecord> = active
+        .iter()
+        .filter(|r| r.role == MountRole::Overlay && touches(r, id, &roots))
+        .collect();
+
+    let old_magic: Vec<&MountRecord> = active
+        .iter()
+        .filter(|r| r.role == MountRole::Magic && touches(r, id, &roots))
+        .collect();
+
+    let live = storage.join(id);
+
+    let backup = storage.join(format!(".graft_{}", id));
+
+    if backup.exists() {
+        fs::remove_dir_all(&backup)?;
+    }
+
+    if live.exists() {
+        fs::rename(&live, &backup).context("Failed to set aside current content")?;
+    }
+
+    if let Some(module) = module {
+        let mut forced = config.clone();
+
+        forced.force_resync = vec![id.to_string()];
+
+        if let Err(e) = sync::sync_module(module, &storage, &forced) {
+            restore_content(&live, &backup)?;
+
+            return Err(e.context(format!("Failed to sync module {}", id)));
+        }
+    }
+
+    let plan = match planner::generate(config, &modules, &storage) {
+        Ok(plan) => plan,
+        Err(e) => {
+            restore_content(&live, &backup)?;
+
+            return Err(e);
+        }
+    };
+
+    let new_overlays: Vec<&OverlayOperation> = plan
+        .overlay_ops
+        .iter()
+        .filter(|op| {
+            op.lowerdirs
+                .iter()
+                .any(|l| l.parent().and_then(|p| p.file_name()) == Some(id.as_ref()))
+        })
+        .collect();
+
+    let targets: BTreeSet<PathBuf> = old_overlays
+        .iter()
+        .map(|r| r.target.clone())
+        .chain(new_overlays.iter().map(|op| PathBuf::from(&op.target)))
+        .collect();
+
+    // A skeleton shared with other modules cannot be detached without
+    // taking their files along, nor left in place while it holds ours.
+    let blocking = active.iter().find(|r| {
+        r.role == MountRole::Magic
+            && if touches(r, id, &roots) {
+                r.module_ids(&roots).iter().any(|m| m != id)
+            } else {
+                targets.iter().any(|t| r.target.starts_with(t))
+            }
+    });
+
+    if let Some(record) = blocking {
+        restore_content(&live, &backup)?;
+
+        bail!(
+            "{} carries magic mounts of other modules; reboot to apply {}",
+            record.target.display(),
+            id
+        );
+    }
+
+    // Every overlay on a touched partition is replaced, whichever modules it
+    // serves; otherwise the new overlay would be stacked on top of it.
+    let stacked: Vec<&MountRecord> = active
+        .iter()
+        .filter(|r| r.role == MountRole::Overlay && targets.iter().any(|t| r.target.starts_with(t)))
+        .collect();
+
+    let remounted: Vec<&OverlayOperation> = plan
+        .overlay_ops
+        .iter()
+        .filter(|op| targets.contains(Path::new(&op.target)))
+        .collect();
+
+    let rollback = Rollback {
+        mark: journal::next_order(),
+        live: live.clone(),
+        backup: backup.clone(),
+        overlays: stacked
+            .iter()
+            .filter(|r| targets.contains(&r.target))
+            .map(|r| {
+                let op = OverlayOperation {
+                    partition_name: r
+                        .target
+                        .file_name()
+                        .map(|n| n.to_string_lossy().to_string())
+                        .unwrap_or_default(),
+                    target: r.target.to_string_lossy().to_string(),
+                    lowerdirs: r.lowerdirs.clone(),
+                    // The target was registered (or not) when first mounted.
+                    umount: UmountPolicy::Never,
+                };
+
+                (r.order, op)
+            })
+            .collect(),
+        magic_orders: old_magic.iter().map(|r| r.order).collect(),
+        magic_roots: old_magic
+            .iter()
+            .flat_map(|r| {
+                std::iter::once(Path::new(&r.source))
+                    .chain(r.contributors.iter().map(PathBuf::as_path))
+            })
+            .filter_map(|source| {
+                [storage.as_path(), Path::new(&config.moduledir)]
+                    .iter()
+                    .find(|base| source.starts_with(base))
+                    .map(|base| base.join(id))
+            })
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect(),
+    };
+
+    let magic_roots: Vec<PathBuf> = plan
+        .magic_module_paths
+        .iter()
+        .filter(|p| p.file_name() == Some(id.as_ref()))
+        .cloned()
+        .collect();
+
+    let result = (|| -> Result<()> {
+        for record in old_magic.iter().rev() {
+            executor::detach(record, &config.mountsource)?;
+        }
+
+        for record in stacked.iter().rev() {
+            executor::detach(record, &config.mountsource)?;
+        }
+
+        for op in &remounted {
+            executor::mount_overlay_op(op, config.disable_umount)?;
+        }
+
+        if !magic_roots.is_empty() {
+            executor::mount_magic(&magic_roots, &plan.magic_scopes, config)?;
+        }
+
+        Ok(())
+    })();
+
+    if let Err(e) = result {
+        log::error!("Hot-apply of {} failed: {:#}. Rolling back.", id, e);
+
+        if let Err(rollback_err) = roll_back(&rollback, &plan.magic_scopes, config) {
+            log::error!("CRITICAL: Rollback of {} failed: {:#}", id, rollback_err);
+        }
+
+        state.mounts = journal::entries();
+
+        let _ = state.save();
+
+        return Err(e.context(format!("Failed to apply module {}", id)));
+    }
+
+    if backup.exists() {
+        let _ = fs::remove_dir_all(&backup);
+    }
+
+    state.overlay_modules.retain(|m| m != id);
+
+    state.magic_modules.retain(|m| m != id);
+
+    if !new_overlays.is_empty() {
+        state.overlay_modules.push(id.to_string());
+    }
+
+    if !magic_roots.is_empty() {
+        state.magic_modules.push(id.to_string());
+    }
+
+    state.overlay_modules.sort();
+
+    state.magic_modules.sort();
+
+    // Partitions left alone keep their boot status, reverted ones included.
+    state
+        .active_mounts
+        .retain(|p| !targets.iter().any(|t| t.file_name() == Some(p.as_ref())));
+
+    state
+        .active_mounts
+        .extend(remounted.iter().map(|op| op.partition_name.clone()));
+
+    state.active_mounts.sort();
+
+    state.active_mounts.dedup();
+
+    state.mounts = journal::entries();
+
+    // Detached targets and everything below them are no longer ours; the
+    // ones that came back were registered again just now.
+    state.umount_paths.retain(|path| {
+        !old_magic
+            .iter()
+            .chain(&stacked)
+            .any(|r| was_detached(r.order) && Path::new(path).starts_with(&r.target))
+    });
+
+    state.umount_paths.extend(try_umount::registered());
+
+    state.umount_paths.sort();
+
+    state.umount_paths.dedup();
+
+    state.save()?;
+
+    log::info!(">> Module {} applied.", id);
+
+    Ok(())
+}
@@ -2,19 +2,28 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 use std::{
+    collections::BTreeMap,
     fs,
     io::Write,
     path::Path,
-    time::{SystemTime, UNIX_EPOCH},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use anyhow::{Context, Result, bail};
 use serde::{Deserialize, Serialize};
 
-use crate::{conf::config::Config, defs};
+use crate::{
+    conf::{config::Config, config_file::ConfigFile},
+    core::{
+        bisect,
+        executor::{ExecutionOutcome, ExecutionStatus},
+        module_prop::ModuleProp,
+    },
+    defs, utils,
+};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
-
 pub struct Silo {
     pub id: String,
     pub timestamp: u64,
@@ -25,10 +34,52 @@ pub struct Silo {
     pub raw_config: Option<String>,
     #[serde(default)]
     pub raw_state: Option<String>,
+    /// Installed modules at snapshot time; `None` for silos that predate it.
+    #[serde(default)]
+    pub modules: Option<Vec<ModuleEntry>>,
+    /// User rule files by file name.
+    #[serde(default)]
+    pub rules: Option<BTreeMap<String, String>>,
+    /// Pinned silos are never pruned.
+    #[serde(default)]
+    pub pinned: bool,
+    /// Set once a boot with this content reached boot completion; Ratoon
+    /// only rolls back to such silos.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub known_good: bool,
+    /// Taken on another setup and brought in by `granary import`. Ratoon
+    /// never rolls back to such silos on its own.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub imported: bool,
+    /// Last time an identical snapshot was requested and folded into this one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_seen: Option<u64>,
+    /// Checksum of the stored JSON without this field; absent in old silos.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+}
+
+/// A silo file that could not be read back intact.
+#[derive(Serialize, Debug, Clone)]
+pub struct CorruptSilo {
+    pub file: String,
+    pub error: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ModuleEntry {
+    pub id: String,
+    #[serde(default)]
+    pub version: String,
+    pub enabled: bool,
+    #[serde(default)]
+    pub skip_mount: bool,
 }
 
 const RATOON_COUNTER_FILE: &str = "/data/adb/meta-hybrid/ratoon_counter";
 
+const RATOON_OUTCOME_FILE: &str = "/data/adb/meta-hybrid/ratoon_outcome.json";
+
 const RATOON_RESCUE_NOTICE: &str = "/data/adb/meta-hybrid/rescue_notice";
 
 const GRANARY_DIR: &str = "/data/adb/meta-hybrid/granary";
@@ -37,7 +88,67 @@ const CONFIG_PATH: &str = "/data/adb/meta-hybrid/config.toml";
 
 const STATE_PATH: &str = "/data/adb/meta-hybrid/state.json";
 
-pub fn engage_ratoon_protocol() -> Result<()> {
+const BOOT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+pub fn write_rescue_notice(notice: &str) {
+    if let Err(e) = fs::write(RATOON_RESCUE_NOTICE, notice) {
+        log::warn!("Failed to write rescue notice: {}", e);
+    }
+}
+
+pub fn read_rescue_notice() -> Option<String> {
+    fs::read_to_string(RATOON_RESCUE_NOTICE).ok()
+}
+
+pub fn dismiss_rescue_notice() -> Result<()> {
+    if Path::new(RATOON_RESCUE_NOTICE).exists() {
+        fs::remove_file(RATOON_RESCUE_NOTICE)?;
+    }
+
+    Ok(())
+}
+
+/// Enabled modules that are new, updated or re-enabled compared to the most
+/// recent silo with a different module set. Falls back to every enabled
+/// module when no such silo exists.
+fn recent_changes(moduledir: &Path) -> Result<Vec<String>> {
+    let current = capture_modules(moduledir)?;
+
+    let enabled: Vec<&ModuleEntry> = current.iter().filter(|m| m.enabled).collect();
+
+    let baseline = list_silos()?
+        .into_iter()
+        .filter(|silo| !silo.imported)
+        .filter_map(|silo| silo.modules)
+        .find(|modules| *modules != current);
+
+    let changed: Vec<String> = match &baseline {
+        Some(baseline) => enabled
+            .iter()
+            .filter(|m| {
+                !baseline
+                    .iter()
+                    .any(|b| b.id == m.id && b.version == m.version && b.enabled)
+            })
+            .map(|m| m.id.clone())
+            .collect(),
+        None => Vec::new(),
+    };
+
+    if changed.is_empty() {
+        return Ok(enabled.iter().map(|m| m.id.clone()).collect());
+    }
+
+    Ok(changed)
+}
+
+pub fn engage_ratoon_protocol(config: &Config) -> Result<()> {
+    let granary = &config.granary;
+
+    if let Some(state) = bisect::load() {
+        return bisect::on_boot(state, &config.moduledir);
+    }
+
     let path = Path::new(RATOON_COUNTER_FILE);
 
     let mut count = 0;
@@ -48,7 +159,7 @@ pub fn engage_ratoon_protocol() -> Result<()> {
         count = content.trim().parse::<u8>().unwrap_or(0);
     }
 
-    count += 1;
+    count = count.saturating_add(1);
 
     // Use explicit file operations to ensure persistence against kernel panic
     {
@@ -63,26 +174,34 @@ pub fn engage_ratoon_protocol() -> Result<()> {
 
     log::info!(">> Ratoon Protocol: Boot counter at {}", count);
 
-    if count >= 3 {
-        log::error!(">> RATOON TRIGGERED: Detected potential bootloop (3 failed boots).");
+    let threshold = granary.ratoon_threshold.max(1);
+
+    if count >= threshold {
+        log::error!(
+            ">> RATOON TRIGGERED: Detected potential bootloop ({} failed boots).",
+            count
+        );
 
         log::warn!(">> Executing emergency rollback from Granary...");
 
-        match restore_latest_silo() {
+        match restore_known_good_silo(&config.moduledir) {
             Ok(silo_id) => {
                 log::info!(">> Rollback successful. Resetting counter.");
 
                 let _ = fs::remove_file(path);
 
                 // Write notice for WebUI/User
-                let notice = format!(
+                write_rescue_notice(&format!(
                     "System recovered from bootloop by restoring snapshot: {}",
                     silo_id
-                );
+                ));
+            }
+            Err(e) if granary.bisect => {
+                log::error!(">> Rollback failed: {}. Starting module bisect.", e);
 
-                if let Err(e) = fs::write(RATOON_RESCUE_NOTICE, notice) {
-                    log::warn!("Failed to write rescue notice: {}", e);
-                }
+                bisect::start(&config.moduledir, recent_changes(&config.moduledir)?)?;
+
+                let _ = fs::remove_file(path);
             }
             Err(e) => {
                 log::error!(
@@ -101,6 +220,82 @@ pub fn engage_ratoon_protocol() -> Result<()> {
     Ok(())
 }
 
+fn boot_completed() -> bool {
+    utils::getprop("sys.boot_completed").as_deref() == Some("1")
+}
+
+fn last_outcome() -> Option<ExecutionOutcome> {
+    let content = fs::read_to_string(RATOON_OUTCOME_FILE).ok()?;
+
+    serde_json::from_str(&content).ok()
+}
+
+/// Waits for Android to report `sys.boot_completed` and only then counts
+/// the boot as successful. A boot that times out, or whose mount plan was
+/// rolled back, keeps the counter for the next Ratoon check.
+pub fn watch_boot_completion(config: &Config) -> Result<()> {
+    let granary = &config.granary;
+
+    let timeout = Duration::from_secs(granary.boot_timeout);
+
+    let start = Instant::now();
+
+    log::info!(
+        ">> Ratoon Watchdog: waiting up to {}s for boot completion...",
+        granary.boot_timeout
+    );
+
+    while !boot_completed() {
+        if start.elapsed() >= timeout {
+            bail!(
+                "Boot did not complete within {}s; Ratoon counter kept",
+                granary.boot_timeout
+            );
+        }
+
+        thread::sleep(BOOT_POLL_INTERVAL);
+    }
+
+    log::info!(
+        ">> Ratoon Watchdog: boot completed after {}s.",
+        start.elapsed().as_secs()
+    );
+
+    if last_outcome().is_some_and(|o| o.status == ExecutionStatus::RolledBack) {
+        log::warn!(">> Mount plan was rolled back this boot. Ratoon counter kept.");
+
+        return Ok(());
+    }
+
+    bisect::on_boot_completed(&config.moduledir)?;
+
+    disengage_ratoon_protocol();
+
+    if let Err(e) = mark_known_good(config) {
+        log::warn!("Failed to mark a known-good silo: {:#}", e);
+    }
+
+    Ok(())
+}
+
+/// Records the setup that just booted as a Ratoon rollback target. An
+/// identical Boot Backup is reused instead of taking a new snapshot.
+fn mark_known_good(config: &Config) -> Result<()> {
+    let id = create_silo(config, "Known Good", "Boot completed")?;
+
+    let mut silo = load_silo(&id)?;
+
+    if !silo.known_good {
+        silo.known_good = true;
+
+        save_silo(&silo)?;
+
+        log::info!(">> Ratoon Watchdog: silo {} marked known-good.", id);
+    }
+
+    Ok(())
+}
+
 pub fn disengage_ratoon_protocol() {
     let path = Path::new(RATOON_COUNTER_FILE);
 
@@ -113,34 +308,337 @@ pub fn disengage_ratoon_protocol() {
     }
 }
 
-pub fn create_silo(config: &Config, label: &str, reason: &str) -> Result<String> {
-    if let Err(e) = fs::create_dir_all(GRANARY_DIR) {
-        log::warn!("Failed to create granary dir: {}", e);
+/// Stores how the last mount transaction ended. A rolled back boot does not
+/// count as successful, so repeated rollbacks still reach the Ratoon
+/// threshold.
+pub fn record_ratoon_outcome(outcome: &ExecutionOutcome) {
+    if outcome.status != ExecutionStatus::Complete {
+        log::warn!(
+            "Ratoon Protocol: mount transaction ended {:?} (reverted: {:?})",
+            outcome.status,
+            outcome.reverted_partitions
+        );
     }
 
-    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let result = serde_json::to_string_pretty(outcome)
+        .map_err(anyhow::Error::from)
+        .and_then(|json| fs::write(RATOON_OUTCOME_FILE, json).map_err(Into::into));
+
+    if let Err(e) = result {
+        log::warn!("Failed to record Ratoon outcome: {}", e);
+    }
+}
+
+fn is_own_entry(id: &str) -> bool {
+    matches!(id, "meta-hybrid" | "lost+found") || id.starts_with('.')
+}
+
+pub fn capture_modules(moduledir: &Path) -> Result<Vec<ModuleEntry>> {
+    let mut modules = Vec::new();
+
+    if !moduledir.exists() {
+        return Ok(modules);
+    }
+
+    for entry in fs::read_dir(moduledir)?.flatten() {
+        let path = entry.path();
+
+        let id = entry.file_name().to_string_lossy().to_string();
+
+        if !path.is_dir() || is_own_entry(&id) {
+            continue;
+        }
+
+        let prop = ModuleProp::from(path.join("module.prop").as_path());
+
+        modules.push(ModuleEntry {
+            id,
+            version: prop.version(),
+            enabled: !path.join(defs::DISABLE_FILE_NAME).exists(),
+            skip_mount: path.join(defs::SKIP_MOUNT_FILE_NAME).exists(),
+        });
+    }
+
+    modules.sort_by(|a, b| a.id.cmp(&b.id));
+
+    Ok(modules)
+}
+
+fn capture_rules() -> Result<BTreeMap<String, String>> {
+    let mut rules = BTreeMap::new();
+
+    let dir = Path::new(defs::RULES_DIR);
+
+    if !dir.exists() {
+        return Ok(rules);
+    }
+
+    for entry in fs::read_dir(dir)?.flatten() {
+        let path = entry.path();
+
+        if path.extension().and_then(|s| s.to_str()) == Some("json") {
+            let name = entry.file_name().to_string_lossy().to_string();
 
-    let id = format!("silo_{}", now);
+            rules.insert(name, fs::read_to_string(&path)?);
+        }
+    }
+
+    Ok(rules)
+}
+
+pub fn set_flag(module_dir: &Path, name: &str, present: bool) -> Result<bool> {
+    let path = module_dir.join(name);
+
+    match (present, path.exists()) {
+        (true, false) => fs::File::create(&path).map(|_| true),
+        (false, true) => fs::remove_file(&path).map(|_| true),
+        _ => Ok(false),
+    }
+    .with_context(|| format!("Failed to update {}", path.display()))
+}
+
+/// Brings the `disable` and `skip_mount` flags back to the snapshot. Modules
+/// installed after the snapshot are disabled.
+fn restore_modules(moduledir: &Path, snapshot: &[ModuleEntry]) -> Result<()> {
+    let current = capture_modules(moduledir)?;
+
+    for module in &current {
+        let module_dir = moduledir.join(&module.id);
+
+        let (enabled, skip_mount) = match snapshot.iter().find(|m| m.id == module.id) {
+            Some(saved) => {
+                if saved.version != module.version {
+                    log::warn!(
+                        ">> Module {} is now {} (snapshot: {}); only flags are restored.",
+                        module.id,
+                        module.version,
+                        saved.version
+                    );
+                }
+
+                (saved.enabled, saved.skip_mount)
+            }
+            None => {
+                log::warn!(">> Module {} is not in the snapshot. Disabling.", module.id);
+
+                (false, module.skip_mount)
+            }
+        };
+
+        if set_flag(&module_dir, defs::DISABLE_FILE_NAME, !enabled)? {
+            log::info!(
+                ">> Module {} {}",
+                module.id,
+                if enabled { "enabled" } else { "disabled" }
+            );
+        }
+
+        set_flag(&module_dir, defs::SKIP_MOUNT_FILE_NAME, skip_mount)?;
+    }
+
+    for saved in snapshot {
+        if !current.iter().any(|m| m.id == saved.id) {
+            log::warn!(">> Module {} was removed since the snapshot.", saved.id);
+        }
+    }
+
+    Ok(())
+}
+
+fn restore_rules(rules: &BTreeMap<String, String>) -> Result<()> {
+    let dir = Path::new(defs::RULES_DIR);
+
+    if dir.exists() {
+        for entry in fs::read_dir(dir)?.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+
+            if name.ends_with(".json") && !rules.contains_key(&name) {
+                fs::remove_file(entry.path())?;
+            }
+        }
+    }
+
+    if rules.is_empty() {
+        return Ok(());
+    }
+
+    fs::create_dir_all(dir)?;
+
+    for (name, content) in rules {
+        if name.contains('/') {
+            log::warn!(">> Skipping invalid rule file name: {}", name);
+
+            continue;
+        }
+
+        fs::write(dir.join(name), content)?;
+    }
+
+    Ok(())
+}
+
+/// Captures the live config, state, module set and rules as a silo
+/// without writing it to the granary.
+pub fn snapshot(config: &Config, id: &str, label: &str, reason: &str) -> Result<Silo> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
 
     let raw_config = fs::read_to_string(CONFIG_PATH).ok();
 
     let raw_state = fs::read_to_string(STATE_PATH).ok();
 
-    let silo = Silo {
-        id: id.clone(),
+    let modules = capture_modules(&config.moduledir)
+        .map_err(|e| log::warn!("Failed to capture module set: {}", e))
+        .ok();
+
+    let rules = capture_rules()
+        .map_err(|e| log::warn!("Failed to capture rules: {}", e))
+        .ok();
+
+    Ok(Silo {
+        id: id.to_string(),
         timestamp: now,
         label: label.to_string(),
         reason: reason.to_string(),
         config_snapshot: config.clone(),
         raw_config,
         raw_state,
-    };
+        modules,
+        rules,
+        pinned: false,
+        known_good: false,
+        imported: false,
+        last_seen: None,
+        checksum: None,
+    })
+}
 
-    let file_path = Path::new(GRANARY_DIR).join(format!("{}.json", id));
+pub fn silo_exists(id: &str) -> bool {
+    Path::new(GRANARY_DIR).join(format!("{}.json", id)).exists()
+}
+
+fn silo_checksum(value: &serde_json::Value) -> Result<String> {
+    Ok(utils::checksum(&serde_json::to_vec(value)?))
+}
+
+/// Writes a silo atomically, stamping it with a fresh checksum.
+pub fn save_silo(silo: &Silo) -> Result<()> {
+    fs::create_dir_all(GRANARY_DIR)?;
+
+    let mut value = serde_json::to_value(silo)?;
+
+    let map = value
+        .as_object_mut()
+        .context("Silo did not serialize to an object")?;
+
+    map.remove("checksum");
+
+    let checksum = silo_checksum(&value)?;
+
+    if let Some(map) = value.as_object_mut() {
+        map.insert("checksum".to_string(), checksum.into());
+    }
+
+    let file_path = Path::new(GRANARY_DIR).join(format!("{}.json", silo.id));
+
+    utils::atomic_write(&file_path, serde_json::to_string_pretty(&value)?.as_bytes())
+}
+
+/// Parses a silo file and checks it against its stored checksum.
+fn read_silo(path: &Path) -> Result<Silo> {
+    let content = fs::read_to_string(path)?;
+
+    let mut value: serde_json::Value =
+        serde_json::from_str(&content).context("Truncated or malformed JSON")?;
+
+    let stored = value
+        .as_object_mut()
+        .context("Not a silo object")?
+        .remove("checksum");
+
+    if let Some(stored) = &stored {
+        let actual = silo_checksum(&value)?;
+
+        let stored = stored.as_str().unwrap_or_default();
+
+        if stored != actual {
+            bail!("Checksum mismatch (stored {}, actual {})", stored, actual);
+        }
+    }
 
-    let json = serde_json::to_string_pretty(&silo)?;
+    let mut silo: Silo = serde_json::from_value(value).context("Invalid silo content")?;
 
-    fs::write(&file_path, json)?;
+    silo.checksum = stored.and_then(|v| v.as_str().map(String::from));
+
+    Ok(silo)
+}
+
+/// Unique id from the creation time, with a sequence number on collision.
+pub fn new_silo_id() -> Result<(String, u64)> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+
+    let base = format!("silo_{}_{:06}", now.as_secs(), now.subsec_micros());
+
+    let id = (0..)
+        .map(|seq| match seq {
+            0 => base.clone(),
+            n => format!("{}_{}", base, n),
+        })
+        .find(|id| !silo_exists(id))
+        .unwrap_or(base);
+
+    Ok((id, now.as_secs()))
+}
+
+/// Fingerprint of what a restore would bring back. The runtime state is
+/// left out since it changes on every boot.
+fn content_key(silo: &Silo) -> Result<String> {
+    let content = serde_json::json!({
+        "config": silo.config_snapshot,
+        "raw_config": silo.raw_config,
+        "modules": silo.modules,
+        "rules": silo.rules,
+    });
+
+    silo_checksum(&content)
+}
+
+pub fn set_pinned(id: &str, pinned: bool) -> Result<()> {
+    let mut silo = load_silo(id)?;
+
+    silo.pinned = pinned;
+
+    save_silo(&silo)
+}
+
+/// Creates a snapshot, or refreshes the newest silo when nothing changed
+/// since it was taken. Returns the id of the silo holding the content.
+pub fn create_silo(config: &Config, label: &str, reason: &str) -> Result<String> {
+    if let Err(e) = fs::create_dir_all(GRANARY_DIR) {
+        log::warn!("Failed to create granary dir: {}", e);
+    }
+
+    let (id, now) = new_silo_id()?;
+
+    let mut silo = snapshot(config, &id, label, reason)?;
+
+    silo.timestamp = now;
+
+    if let Some(mut latest) = list_silos()?.into_iter().find(|s| !s.imported)
+        && content_key(&latest)? == content_key(&silo)?
+    {
+        log::info!(
+            "Granary: nothing changed since {}, refreshing it instead.",
+            latest.id
+        );
+
+        latest.last_seen = Some(now);
+
+        save_silo(&latest)?;
+
+        return Ok(latest.id);
+    }
+
+    save_silo(&silo)?;
 
     if let Err(e) = prune_silos(config) {
         log::warn!("Failed to prune granary: {}", e);
@@ -149,32 +647,54 @@ pub fn create_silo(config: &Config, label: &str, reason: &str) -> Result<String>
     Ok(id)
 }
 
-pub fn list_silos() -> Result<Vec<Silo>> {
-    let mut silos = Vec::new();
-
+fn silo_files() -> Result<Vec<std::path::PathBuf>> {
     if !Path::new(GRANARY_DIR).exists() {
-        return Ok(silos);
+        return Ok(Vec::new());
     }
 
-    for entry in fs::read_dir(GRANARY_DIR)? {
-        let entry = entry?;
+    let mut files = Vec::new();
 
-        let path = entry.path();
+    for entry in fs::read_dir(GRANARY_DIR)? {
+        let path = entry?.path();
 
         if path.extension().and_then(|s| s.to_str()) == Some("json") {
-            let content = fs::read_to_string(&path)?;
+            files.push(path);
+        }
+    }
 
-            if let Ok(silo) = serde_json::from_str::<Silo>(&content) {
-                silos.push(silo);
-            }
+    Ok(files)
+}
+
+/// Intact silos, newest first. Damaged files are logged and left out; see
+/// [`check_silos`].
+pub fn list_silos() -> Result<Vec<Silo>> {
+    let mut silos = Vec::new();
+
+    for path in silo_files()? {
+        match read_silo(&path) {
+            Ok(silo) => silos.push(silo),
+            Err(e) => log::warn!("Granary: corrupted silo {}: {:#}", path.display(), e),
         }
     }
 
-    silos.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+    silos.sort_by(|a, b| b.timestamp.cmp(&a.timestamp).then_with(|| b.id.cmp(&a.id)));
 
     Ok(silos)
 }
 
+/// Silo files that fail to parse or do not match their checksum.
+pub fn check_silos() -> Result<Vec<CorruptSilo>> {
+    Ok(silo_files()?
+        .into_iter()
+        .filter_map(|path| {
+            read_silo(&path).err().map(|e| CorruptSilo {
+                file: path.display().to_string(),
+                error: format!("{:#}", e),
+            })
+        })
+        .collect())
+}
+
 pub fn delete_silo(id: &str) -> Result<()> {
     let file_path = Path::new(GRANARY_DIR).join(format!("{}.json", id));
 
@@ -189,17 +709,116 @@ pub fn delete_silo(id: &str) -> Result<()> {
     }
 }
 
-pub fn restore_silo(id: &str) -> Result<()> {
+pub fn load_silo(id: &str) -> Result<Silo> {
     let file_path = Path::new(GRANARY_DIR).join(format!("{}.json", id));
 
     if !file_path.exists() {
         bail!("Silo {} not found", id);
     }
 
-    let content = fs::read_to_string(&file_path)?;
+    read_silo(&file_path).with_context(|| format!("Silo {} is corrupted", id))
+}
+
+/// Parts of a silo to bring back. An empty scope restores everything.
+#[derive(Debug, Default, Clone)]
+pub struct RestoreScope {
+    pub winnowing: bool,
+    pub partitions: bool,
+    /// Modules whose rule file is restored.
+    pub rules: Vec<String>,
+}
+
+impl RestoreScope {
+    pub fn is_full(&self) -> bool {
+        !self.winnowing && !self.partitions && self.rules.is_empty()
+    }
+}
+
+/// Restores `id` (or part of it) after saving the live setup, so the
+/// restore can be undone. Returns the id of that pre-restore silo.
+pub fn restore(config: &Config, id: &str, scope: &RestoreScope) -> Result<String> {
+    // Load first: the snapshot below may prune the silo being restored.
+    let silo = load_silo(id)?;
+
+    for module in &scope.rules {
+        utils::validate_module_id(module)?;
+    }
+
+    let undo = create_silo(config, "Pre-Restore", &format!("Before restoring {}", id))?;
+
+    if scope.is_full() {
+        apply_silo(&silo)?;
+    } else {
+        apply_parts(&silo, scope)?;
+    }
+
+    Ok(undo)
+}
+
+fn apply_parts(silo: &Silo, scope: &RestoreScope) -> Result<()> {
+    log::info!(">> Partially restoring Silo: {} ({})", silo.id, silo.label);
+
+    if scope.winnowing || scope.partitions {
+        let mut live = ConfigFile::load(Path::new(CONFIG_PATH))?;
+
+        if scope.winnowing {
+            log::info!(
+                ">> Restoring winnowing table ({} rules)...",
+                silo.config_snapshot.winnowing.rules.len()
+            );
+
+            live.set("winnowing", &silo.config_snapshot.winnowing)?;
+        }
+
+        if scope.partitions {
+            log::info!(
+                ">> Restoring partitions: {:?}",
+                silo.config_snapshot.partitions
+            );
+
+            live.set("partitions", &silo.config_snapshot.partitions)?;
+        }
+
+        live.save(Path::new(CONFIG_PATH))?;
+    }
+
+    if scope.rules.is_empty() {
+        return Ok(());
+    }
+
+    let Some(rules) = &silo.rules else {
+        bail!("Silo {} does not capture module rules", silo.id);
+    };
+
+    let dir = Path::new(defs::RULES_DIR);
+
+    for module in &scope.rules {
+        let name = format!("{}.json", module);
+
+        let path = dir.join(&name);
+
+        match rules.get(&name) {
+            Some(content) => {
+                log::info!(">> Restoring rules of {}", module);
+
+                fs::create_dir_all(dir)?;
+
+                fs::write(&path, content)?;
+            }
+            None => {
+                log::info!(">> {} had no rules in the snapshot. Removing.", module);
+
+                if path.exists() {
+                    fs::remove_file(&path)?;
+                }
+            }
+        }
+    }
 
-    let silo: Silo = serde_json::from_str(&content)?;
+    Ok(())
+}
 
+fn apply_silo(silo: &Silo) -> Result<()> {
     log::info!(">> Restoring Silo: {} ({})", silo.id, silo.label);
 
     if let Some(raw) = &silo.raw_config {
@@ -222,18 +841,44 @@ pub fn restore_silo(id: &str) -> Result<()> {
         log::warn!(">> No state snapshot found in this Silo. Skipping state restore.");
     }
 
+    if let Some(rules) = &silo.rules {
+        log::info!(">> Restoring {} rule files...", rules.len());
+
+        restore_rules(rules)?;
+    }
+
+    if let Some(modules) = &silo.modules {
+        log::info!(">> Restoring module set ({} modules)...", modules.len());
+
+        restore_modules(&silo.config_snapshot.moduledir, modules)?;
+    } else {
+        log::warn!(">> No module set in this Silo. Module flags left as they are.");
+    }
+
     Ok(())
 }
 
-fn restore_latest_silo() -> Result<String> {
-    let silos = list_silos()?;
+/// Restores the newest silo known to boot, or failing that the newest one
+/// whose module set differs from the installed one. The newest silo alone
+/// is no use: the Boot Backup of a looping boot already holds the bad set.
+fn restore_known_good_silo(moduledir: &Path) -> Result<String> {
+    let silos: Vec<Silo> = list_silos()?.into_iter().filter(|s| !s.imported).collect();
 
-    if let Some(latest) = silos.first() {
-        restore_silo(&latest.id)?;
+    let current = capture_modules(moduledir)?;
 
-        Ok(latest.id.clone())
-    } else {
-        bail!("No silos found in Granary");
+    let target = silos.iter().find(|s| s.known_good).or_else(|| {
+        silos
+            .iter()
+            .find(|s| s.modules.as_ref().is_some_and(|m| *m != current))
+    });
+
+    match target {
+        Some(silo) => {
+            apply_silo(silo)?;
+
+            Ok(silo.id.clone())
+        }
+        None => bail!("No silo in the Granary differs from the failing setup"),
     }
 }
 
@@ -254,14 +899,23 @@ fn prune_silos(config: &Config) -> Result<()> {
         0
     };
 
-    for (i, silo) in silos.iter().enumerate() {
+    // The newest known-good silo is the Ratoon rollback target; keep it.
+    let rescue = silos.iter().find(|s| s.known_good).map(|s| s.id.clone());
+
+    for (i, silo) in silos
+        .iter()
+        .filter(|s| !s.pinned && Some(&s.id) != rescue.as_ref())
+        .enumerate()
+    {
         let mut should_delete = false;
 
         if max_count > 0 && i >= max_count {
             should_delete = true;
         }
 
-        if retention_days > 0 && silo.timestamp < expiration_ts && i > 0 {
+        let seen = silo.last_seen.unwrap_or(silo.timestamp);
+
+        if retention_days > 0 && seen < expiration_ts && i > 0 {
             should_delete = true;
         }
 
@@ -2,7 +2,7 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fs,
     path::{Path, PathBuf},
 };
@@ -11,11 +11,14 @@ use anyhow::Result;
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 
-use crate::{conf::config, defs};
+use crate::{
+    conf::config::{self, DependencyPolicy},
+    core::module_prop::ModuleProp,
+    defs, utils,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
 #[serde(rename_all = "lowercase")]
-
 pub enum MountMode {
     #[default]
     Overlay,
@@ -23,17 +26,113 @@ pub enum MountMode {
     Ignore,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+/// Whether a mount is registered for KernelSU's try-umount, which hides it
+/// from apps on the denylist.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum UmountPolicy {
+    /// Follow the global `disable_umount` setting.
+    #[default]
+    Default,
+    Always,
+    Never,
+}
+
+impl UmountPolicy {
+    pub fn is_default(&self) -> bool {
+        *self == Self::Default
+    }
+
+    /// Policy of a mount point shared by several modules: one module asking
+    /// to stay visible keeps the whole mount visible.
+    pub fn combine(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::Never, _) | (_, Self::Never) => Self::Never,
+            (Self::Always, _) | (_, Self::Always) => Self::Always,
+            _ => Self::Default,
+        }
+    }
+
+    /// True when the mount should be registered, given the global default.
+    pub fn should_register(self, default: bool) -> bool {
+        match self {
+            Self::Default => default,
+            Self::Always => true,
+            Self::Never => false,
+        }
+    }
+}
 
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ModuleRules {
     #[serde(default)]
     pub default_mode: MountMode,
     #[serde(default)]
     pub paths: HashMap<String, MountMode>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<i32>,
+    /// Modules that must be enabled for this one to work.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub requires: Vec<String>,
+    /// Modules that must not be mounted together with this one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conflicts: Vec<String>,
+    /// Modules this one supersedes; they are left out while it is enabled.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub replaces: Vec<String>,
+    /// Mount even if `module.prop` says the device is not supported. Only
+    /// honored in the user's rule file.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ignore_compat: bool,
+    #[serde(default, skip_serializing_if = "UmountPolicy::is_default")]
+    pub umount: UmountPolicy,
+    /// Per-path overrides of `umount`, relative to the module root.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub umount_paths: HashMap<String, UmountPolicy>,
+}
+
+fn merge_ids(into: &mut Vec<String>, ids: Vec<String>) {
+    for id in ids {
+        if !into.contains(&id) {
+            into.push(id);
+        }
+    }
 }
 
 impl ModuleRules {
-    pub fn load(module_dir: &Path, module_id: &str) -> Self {
+    /// Layers user-provided rules on top of these.
+    fn merge(&mut self, user: ModuleRules) {
+        self.default_mode = user.default_mode;
+
+        self.paths.extend(user.paths);
+
+        if user.priority.is_some() {
+            self.priority = user.priority;
+        }
+
+        merge_ids(&mut self.requires, user.requires);
+
+        merge_ids(&mut self.conflicts, user.conflicts);
+
+        merge_ids(&mut self.replaces, user.replaces);
+
+        self.ignore_compat |= user.ignore_compat;
+
+        if !user.umount.is_default() {
+            self.umount = user.umount;
+        }
+
+        self.umount_paths.extend(user.umount_paths);
+    }
+
+    /// Merges the module's `hybrid_rules.json`, the user's rule file, the
+    /// active profile's override and the dependency keys of `module.prop`.
+    pub fn load(
+        module_dir: &Path,
+        module_id: &str,
+        prop: &ModuleProp,
+        profile: Option<&ModuleRules>,
+    ) -> Self {
         let mut rules = ModuleRules::default();
 
         let internal_config = module_dir.join("hybrid_rules.json");
@@ -41,54 +140,371 @@ impl ModuleRules {
         if internal_config.exists() {
             match fs::read_to_string(&internal_config) {
                 Ok(content) => match serde_json::from_str::<ModuleRules>(&content) {
-                    Ok(r) => rules = r,
+                    Ok(r) => {
+                        rules = r;
+
+                        rules.ignore_compat = false;
+                    }
                     Err(e) => log::warn!("Failed to parse rules for module '{}': {}", module_id, e),
                 },
                 Err(e) => log::warn!("Failed to read rule file for '{}': {}", module_id, e),
             }
         }
 
-        let user_rules_dir = Path::new("/data/adb/meta-hybrid/rules");
+        let user_rules_dir = Path::new(defs::RULES_DIR);
 
         let user_config = user_rules_dir.join(format!("{}.json", module_id));
 
         if user_config.exists() {
             match fs::read_to_string(&user_config) {
                 Ok(content) => match serde_json::from_str::<ModuleRules>(&content) {
-                    Ok(user_rules) => {
-                        rules.default_mode = user_rules.default_mode;
-
-                        rules.paths.extend(user_rules.paths);
-                    }
+                    Ok(user_rules) => rules.merge(user_rules),
                     Err(e) => log::warn!("Failed to parse user rules for '{}': {}", module_id, e),
                 },
                 Err(e) => log::warn!("Failed to read user rule file for '{}': {}", module_id, e),
             }
         }
 
+        if let Some(profile) = profile {
+            rules.merge(profile.clone());
+        }
+
+        merge_ids(&mut rules.requires, prop.requires());
+
+        merge_ids(&mut rules.conflicts, prop.conflicts());
+
+        merge_ids(&mut rules.replaces, prop.replaces());
+
+        rules.paths = rules
+            .paths
+            .into_iter()
+            .map(|(k, v)| (k.trim_matches('/').to_string(), v))
+            .collect();
+
+        rules.umount_paths = rules
+            .umount_paths
+            .into_iter()
+            .map(|(k, v)| (k.trim_matches('/').to_string(), v))
+            .collect();
+
         rules
     }
 
+    /// Umount policy of a path relative to the module root, taken from the
+    /// closest rule on the path or one of its parents.
+    pub fn umount_policy(&self, relative_path: &str) -> UmountPolicy {
+        let mut key = relative_path.trim_matches('/');
+
+        loop {
+            if let Some(policy) = self.umount_paths.get(key) {
+                return *policy;
+            }
+
+            match key.rfind('/') {
+                Some(idx) => key = &key[..idx],
+                None => break,
+            }
+        }
+
+        self.umount
+    }
+
+    /// Combined policy of `partition` and every path rule below it, for
+    /// mounts that cover the whole partition.
+    pub fn partition_umount(&self, partition: &str) -> UmountPolicy {
+        let prefix = format!("{}/", partition);
+
+        self.umount_paths
+            .iter()
+            .filter(|(k, _)| k.starts_with(&prefix))
+            .fold(self.umount_policy(partition), |acc, (_, p)| acc.combine(*p))
+    }
+
+    /// Resolves the mode of a path relative to the module root using the
+    /// closest rule on the path itself or one of its parent directories.
     pub fn get_mode(&self, relative_path: &str) -> MountMode {
-        if let Some(mode) = self.paths.get(relative_path) {
-            return mode.clone();
+        let mut key = relative_path.trim_matches('/');
+
+        loop {
+            if let Some(mode) = self.paths.get(key) {
+                return mode.clone();
+            }
+
+            match key.rfind('/') {
+                Some(idx) => key = &key[..idx],
+                None => break,
+            }
         }
 
         self.default_mode.clone()
     }
+
+    /// True when the path and every directory above it resolve to overlay,
+    /// i.e. it may stay inside the partition's OverlayFS lowerdir.
+    pub fn is_overlay_capable(&self, relative_path: &str) -> bool {
+        let key = relative_path.trim_matches('/');
+
+        key.match_indices('/')
+            .map(|(idx, _)| &key[..idx])
+            .chain(std::iter::once(key))
+            .all(|k| self.get_mode(k) == MountMode::Overlay)
+    }
+
+    /// Rule keys nested below `partition`, shallowest first.
+    pub fn nested_paths(&self, partition: &str) -> Vec<&str> {
+        let prefix = format!("{}/", partition);
+
+        let mut keys: Vec<&str> = self
+            .paths
+            .keys()
+            .map(|k| k.as_str())
+            .filter(|k| k.starts_with(&prefix))
+            .collect();
+
+        keys.sort_by_key(|k| (k.len(), *k));
+
+        keys
+    }
+
+    /// Outermost sub-trees of an overlay partition that must be kept out of
+    /// its lowerdir because they are magic mounted or ignored.
+    pub fn carved_paths(&self, partition: &str) -> Vec<&str> {
+        let mut carved: Vec<&str> = Vec::new();
+
+        for key in self.nested_paths(partition) {
+            if self.is_overlay_capable(key) {
+                continue;
+            }
+
+            if carved.iter().any(|c| {
+                key.strip_prefix(c)
+                    .is_some_and(|rest| rest.starts_with('/'))
+            }) {
+                continue;
+            }
+
+            carved.push(key);
+        }
+
+        carved
+    }
 }
 
 #[derive(Debug, Clone)]
-
 pub struct Module {
     pub id: String,
     pub source_path: PathBuf,
     pub rules: ModuleRules,
+    pub priority: i32,
+    pub prop: ModuleProp,
+}
+
+/// Properties of the running device that modules can be gated on. Unknown
+/// values disable the matching gate.
+#[derive(Debug, Clone, Default)]
+pub struct DeviceProfile {
+    pub sdk: Option<u32>,
+    pub device: Option<String>,
+    pub abis: Vec<String>,
+}
+
+impl DeviceProfile {
+    pub fn detect() -> Self {
+        Self {
+            sdk: utils::getprop("ro.build.version.sdk").and_then(|v| v.parse().ok()),
+            device: utils::getprop("ro.product.device"),
+            abis: utils::getprop("ro.product.cpu.abilist")
+                .map(|v| v.split(',').map(|abi| abi.trim().to_string()).collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Why `prop` rules the module out on this device, if it does.
+    pub fn incompatibility(&self, prop: &ModuleProp) -> Option<String> {
+        if let Some(sdk) = self.sdk {
+            if let Some(min) = prop.min_api()
+                && sdk < min
+            {
+                return Some(format!("needs API {} or newer (device has {})", min, sdk));
+            }
+
+            if let Some(max) = prop.max_api()
+                && sdk > max
+            {
+                return Some(format!("needs API {} or older (device has {})", max, sdk));
+            }
+        }
+
+        let devices = prop.devices();
+
+        if let Some(device) = &self.device
+            && !devices.is_empty()
+            && !devices.contains(device)
+        {
+            return Some(format!(
+                "made for {} (device is {})",
+                devices.join(", "),
+                device
+            ));
+        }
+
+        let abis = prop.abis();
+
+        if !self.abis.is_empty() && !abis.is_empty() && !abis.iter().any(|a| self.abis.contains(a))
+        {
+            return Some(format!(
+                "ships {} binaries (device supports {})",
+                abis.join(", "),
+                self.abis.join(", ")
+            ));
+        }
+
+        None
+    }
+}
+
+/// A module left out of the plan, or kept despite a problem.
+#[derive(Debug, Clone, Serialize)]
+pub struct ModuleIssue {
+    pub id: String,
+    pub reason: String,
+    /// False when the policy kept the module in the plan anyway.
+    pub dropped: bool,
+}
+
+/// Result of a scan: the modules to mount plus the ones left out.
+#[derive(Debug, Default)]
+pub struct ScanReport {
+    pub modules: Vec<Module>,
+    pub dropped: Vec<Module>,
+    pub issues: Vec<ModuleIssue>,
+}
+
+/// Applies `replaces`, `conflicts` and `requires` in that order. Modules are
+/// expected in stacking order, so a conflict is won by the upper module.
+fn resolve_dependencies(modules: Vec<Module>, policy: DependencyPolicy) -> ScanReport {
+    let apply = policy == DependencyPolicy::Skip;
+
+    let mut active: HashSet<String> = modules.iter().map(|m| m.id.clone()).collect();
+
+    let mut issues: Vec<ModuleIssue> = Vec::new();
+
+    let flag =
+        |active: &mut HashSet<String>, issues: &mut Vec<ModuleIssue>, id: &str, reason: String| {
+            if apply {
+                active.remove(id);
+            }
+
+            issues.push(ModuleIssue {
+                id: id.to_string(),
+                reason,
+                dropped: apply,
+            });
+        };
+
+    for m in &modules {
+        if !active.contains(&m.id) {
+            continue;
+        }
+
+        for old in &m.rules.replaces {
+            if *old != m.id && active.contains(old) {
+                flag(
+                    &mut active,
+                    &mut issues,
+                    old,
+                    format!("replaced by '{}'", m.id),
+                );
+            }
+        }
+    }
+
+    for (i, m) in modules.iter().enumerate() {
+        if !active.contains(&m.id) {
+            continue;
+        }
+
+        for other in &modules[i + 1..] {
+            if !active.contains(&other.id) {
+                continue;
+            }
+
+            if m.rules.conflicts.contains(&other.id) || other.rules.conflicts.contains(&m.id) {
+                flag(
+                    &mut active,
+                    &mut issues,
+                    &other.id,
+                    format!("conflicts with '{}', which stacks above it", m.id),
+                );
+            }
+        }
+    }
+
+    // Dropping a module may leave others without a dependency.
+    loop {
+        let mut changed = false;
+
+        for m in &modules {
+            if !active.contains(&m.id) || issues.iter().any(|i| i.id == m.id) {
+                continue;
+            }
+
+            if let Some(missing) = m.rules.requires.iter().find(|r| !active.contains(*r)) {
+                flag(
+                    &mut active,
+                    &mut issues,
+                    &m.id,
+                    format!("requires '{}', which is not enabled", missing),
+                );
+
+                changed = true;
+            }
+        }
+
+        if !changed || !apply {
+            break;
+        }
+    }
+
+    for issue in &issues {
+        if issue.dropped {
+            log::warn!(">> Skipping module {}: {}", issue.id, issue.reason);
+        } else {
+            log::warn!(">> Module {} {} (kept by policy)", issue.id, issue.reason);
+        }
+    }
+
+    let (modules, dropped) = modules.into_iter().partition(|m| active.contains(&m.id));
+
+    ScanReport {
+        modules,
+        dropped,
+        issues,
+    }
+}
+
+/// Config overrides win over the module's own rules; modules without any
+/// priority default to 0.
+fn effective_priority(config: &config::Config, id: &str, rules: &ModuleRules) -> i32 {
+    config
+        .priorities
+        .get(id)
+        .copied()
+        .or(rules.priority)
+        .unwrap_or(0)
+}
+
+/// Modules are returned in stacking order: higher priority first, ties broken
+/// by reverse id. The first module ends up as the topmost layer.
+pub fn scan(source_dir: &Path, config: &config::Config) -> Result<Vec<Module>> {
+    Ok(scan_report(source_dir, config)?.modules)
 }
 
-pub fn scan(source_dir: &Path, _config: &config::Config) -> Result<Vec<Module>> {
+/// Like [`scan`], but also returns the modules left out by the device gates
+/// or dependency resolution and why.
+pub fn scan_report(source_dir: &Path, config: &config::Config) -> Result<ScanReport> {
     if !source_dir.exists() {
-        return Ok(Vec::new());
+        return Ok(ScanReport::default());
     }
 
     let dir_entries = fs::read_dir(source_dir)?.collect::<std::io::Result<Vec<_>>>()?;
@@ -119,17 +535,176 @@ pub fn scan(source_dir: &Path, _config: &config::Config) -> Result<Vec<Module>>
                 return None;
             }
 
-            let rules = ModuleRules::load(&path, &id);
+            let prop = ModuleProp::from(path.join("module.prop").as_path());
+
+            let profile_rules = config.active_profile().and_then(|p| p.rules.get(&id));
+
+            let rules = ModuleRules::load(&path, &id, &prop, profile_rules);
+
+            let priority = effective_priority(config, &id, &rules);
 
             Some(Module {
                 id,
                 source_path: path,
                 rules,
+                priority,
+                prop,
             })
         })
         .collect();
 
-    modules.sort_by(|a, b| b.id.cmp(&a.id));
+    modules.sort_by(|a, b| b.priority.cmp(&a.priority).then_with(|| b.id.cmp(&a.id)));
+
+    let device = DeviceProfile::detect();
+
+    let mut incompatible = Vec::new();
+
+    let mut issues = Vec::new();
+
+    modules.retain(|m| {
+        let Some(reason) = device.incompatibility(&m.prop) else {
+            return true;
+        };
+
+        if m.rules.ignore_compat {
+            log::warn!(
+                ">> Module {} {}; mounting anyway (user override)",
+                m.id,
+                reason
+            );
+
+            issues.push(ModuleIssue {
+                id: m.id.clone(),
+                reason,
+                dropped: false,
+            });
+
+            return true;
+        }
+
+        log::warn!(">> Skipping module {}: {}", m.id, reason);
 
-    Ok(modules)
+        issues.push(ModuleIssue {
+            id: m.id.clone(),
+            reason,
+            dropped: true,
+        });
+
+        incompatible.push(m.clone());
+
+        false
+    });
+
+    let mut report = resolve_dependencies(modules, config.dependency_policy);
+
+    report.dropped.extend(incompatible);
+
+    issues.append(&mut report.issues);
+
+    report.issues = issues;
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn module(id: &str, requires: &[&str], conflicts: &[&str], replaces: &[&str]) -> Module {
+        let ids = |list: &[&str]| list.iter().map(|s| s.to_string()).collect();
+
+        Module {
+            id: id.to_string(),
+            source_path: PathBuf::from("/data/adb/modules").join(id),
+            rules: ModuleRules {
+                requires: ids(requires),
+                conflicts: ids(conflicts),
+                replaces: ids(replaces),
+                ..Default::default()
+            },
+            priority: 0,
+            prop: ModuleProp::default(),
+        }
+    }
+
+    fn ids(modules: &[Module]) -> Vec<&str> {
+        modules.iter().map(|m| m.id.as_str()).collect()
+    }
+
+    #[test]
+    fn replaced_module_is_dropped() {
+        let report = resolve_dependencies(
+            vec![
+                module("new", &[], &[], &["old"]),
+                module("old", &[], &[], &[]),
+            ],
+            DependencyPolicy::Skip,
+        );
+
+        assert_eq!(ids(&report.modules), ["new"]);
+
+        assert_eq!(ids(&report.dropped), ["old"]);
+    }
+
+    #[test]
+    fn conflict_is_won_by_the_upper_module() {
+        let report = resolve_dependencies(
+            vec![
+                module("upper", &[], &[], &[]),
+                module("lower", &[], &["upper"], &[]),
+            ],
+            DependencyPolicy::Skip,
+        );
+
+        assert_eq!(ids(&report.modules), ["upper"]);
+
+        assert_eq!(report.issues[0].id, "lower");
+    }
+
+    #[test]
+    fn missing_requirements_cascade() {
+        let report = resolve_dependencies(
+            vec![
+                module("a", &["b"], &[], &[]),
+                module("b", &["c"], &[], &[]),
+                module("d", &[], &[], &[]),
+            ],
+            DependencyPolicy::Skip,
+        );
+
+        assert_eq!(ids(&report.modules), ["d"]);
+
+        assert_eq!(ids(&report.dropped), ["a", "b"]);
+    }
+
+    #[test]
+    fn requirement_lost_to_a_replacement() {
+        let report = resolve_dependencies(
+            vec![
+                module("fork", &[], &[], &["lib"]),
+                module("lib", &[], &[], &[]),
+                module("app", &["lib"], &[], &[]),
+            ],
+            DependencyPolicy::Skip,
+        );
+
+        assert_eq!(ids(&report.modules), ["fork"]);
+    }
+
+    #[test]
+    fn warn_policy_keeps_modules() {
+        let report = resolve_dependencies(
+            vec![
+                module("a", &["missing"], &[], &[]),
+                module("b", &[], &["a"], &[]),
+            ],
+            DependencyPolicy::Warn,
+        );
+
+        assert_eq!(ids(&report.modules), ["a", "b"]);
+
+        assert_eq!(report.issues.len(), 2);
+
+        assert!(report.issues.iter().all(|i| !i.dropped));
+    }
 }
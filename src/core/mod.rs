@@ -1,21 +1,28 @@
 // Copyright 2025 Meta-Hybrid Mount Authors
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+pub mod bisect;
 pub mod executor;
+pub mod graft;
 pub mod granary;
 pub mod inventory;
+pub mod module_prop;
 pub mod modules;
 pub mod planner;
+pub mod profile;
+pub mod silo_archive;
+pub mod silo_diff;
 pub mod state;
 pub mod storage;
 pub mod sync;
+pub mod verify;
 pub mod winnow;
 
-use std::path::Path;
+use std::{collections::BTreeMap, path::Path};
 
 use anyhow::Result;
 
-use crate::{conf::config::Config, try_umount};
+use crate::{conf::config::Config, mount::journal, try_umount};
 
 pub struct Init;
 
@@ -26,11 +33,13 @@ pub struct StorageReady {
 pub struct ModulesReady {
     pub handle: storage::StorageHandle,
     pub modules: Vec<inventory::Module>,
+    pub sync_stats: BTreeMap<String, sync::SyncStats>,
 }
 
 pub struct Planned {
     pub handle: storage::StorageHandle,
     pub modules: Vec<inventory::Module>,
+    pub sync_stats: BTreeMap<String, sync::SyncStats>,
     pub plan: planner::MountPlan,
 }
 
@@ -38,6 +47,7 @@ pub struct Executed {
     pub handle: storage::StorageHandle,
     #[allow(dead_code)]
     pub modules: Vec<inventory::Module>,
+    pub sync_stats: BTreeMap<String, sync::SyncStats>,
     pub plan: planner::MountPlan,
     pub result: executor::ExecutionResult,
 }
@@ -60,6 +70,8 @@ impl OryzaEngine<Init> {
         mnt_base: &Path,
         img_path: &Path,
     ) -> Result<OryzaEngine<StorageReady>> {
+        journal::reset();
+
         let handle = storage::setup(
             mnt_base,
             img_path,
@@ -88,7 +100,8 @@ impl OryzaEngine<StorageReady> {
             modules.len()
         );
 
-        sync::perform_sync(&modules, &self.state.handle.mount_point)?;
+        let sync_stats =
+            sync::perform_sync(&modules, &self.state.handle.mount_point, &self.config)?;
 
         self.state.handle.commit(self.config.disable_umount)?;
 
@@ -97,6 +110,7 @@ impl OryzaEngine<StorageReady> {
             state: ModulesReady {
                 handle: self.state.handle,
                 modules,
+                sync_stats,
             },
         })
     }
@@ -117,6 +131,7 @@ impl OryzaEngine<ModulesReady> {
             state: Planned {
                 handle: self.state.handle,
                 modules: self.state.modules,
+                sync_stats: self.state.sync_stats,
                 plan,
             },
         })
@@ -134,6 +149,7 @@ impl OryzaEngine<Planned> {
             state: Executed {
                 handle: self.state.handle,
                 modules: self.state.modules,
+                sync_stats: self.state.sync_stats,
                 plan: self.state.plan,
                 result,
             },
@@ -177,9 +193,14 @@ impl OryzaEngine<Executed> {
             .overlay_ops
             .iter()
             .map(|op| op.partition_name.clone())
+            .filter(|p| !self.state.result.outcome.reverted_partitions.contains(p))
             .collect();
 
-        let state = state::RuntimeState::new(
+        let outcome = self.state.result.outcome;
+
+        granary::record_ratoon_outcome(&outcome);
+
+        let mut state = state::RuntimeState::new(
             self.state.handle.mode,
             self.state.handle.mount_point,
             self.state.result.overlay_module_ids,
@@ -187,14 +208,20 @@ impl OryzaEngine<Executed> {
             nuke_active,
             active_mounts,
             storage_stats,
+            self.state.sync_stats,
+            journal::entries(),
+            outcome,
         );
 
+        state.profile = self
+            .config
+            .active_profile()
+            .and(self.config.profile.clone());
+
         if let Err(e) = state.save() {
             log::error!("Failed to save runtime state: {:#}", e);
         }
 
-        granary::disengage_ratoon_protocol();
-
         log::info!(">> System operational. Mount sequence complete.");
 
         Ok(())
@@ -0,0 +1,268 @@
+// Copyright 2025 Meta-Hybrid Mount Authors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{collections::BTreeMap, fmt, fs, path::Path};
+
+use anyhow::{Context, Result};
+use serde::{Serialize, Serializer};
+
+use crate::utils;
+
+#[derive(Debug, Clone)]
+struct Line {
+    raw: String,
+    /// Key and trimmed value; `None` for comments, blanks and junk.
+    entry: Option<(String, String)>,
+}
+
+/// A `module.prop` file that round-trips byte for byte. Comments, unknown
+/// keys and line order are kept; only lines touched by [`ModuleProp::set`]
+/// are rewritten.
+#[derive(Debug, Clone, Default)]
+pub struct ModuleProp {
+    lines: Vec<Line>,
+    trailing_newline: bool,
+}
+
+fn split_ids(value: &str) -> Vec<String> {
+    value
+        .split([',', ' '])
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+impl ModuleProp {
+    pub fn parse(content: &str) -> Self {
+        // Split by hand: `str::lines` would swallow a `\r` and the
+        // round trip would no longer be exact.
+        let body = content.strip_suffix('\n').unwrap_or(content);
+
+        let lines = body
+            .split('\n')
+            .filter(|_| !content.is_empty())
+            .map(|raw| {
+                let trimmed = raw.trim_start();
+
+                let entry = if trimmed.starts_with('#') {
+                    None
+                } else {
+                    trimmed
+                        .split_once('=')
+                        .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+                        .filter(|(k, _)| !k.is_empty())
+                };
+
+                Line {
+                    raw: raw.to_string(),
+                    entry,
+                }
+            })
+            .collect();
+
+        Self {
+            lines,
+            trailing_newline: content.ends_with('\n'),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+
+        Ok(Self::parse(&content))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        utils::atomic_write(path, self.to_string().as_bytes())
+    }
+
+    /// Value of `key`; a later line overrides an earlier one.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.lines
+            .iter()
+            .rev()
+            .filter_map(|l| l.entry.as_ref())
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Rewrites every line holding `key`, or appends one.
+    pub fn set(&mut self, key: &str, value: &str) {
+        let mut found = false;
+
+        for line in &mut self.lines {
+            if line.entry.as_ref().is_some_and(|(k, _)| k == key) {
+                line.raw = format!("{}={}", key, value);
+
+                line.entry = Some((key.to_string(), value.trim().to_string()));
+
+                found = true;
+            }
+        }
+
+        if !found {
+            self.lines.push(Line {
+                raw: format!("{}={}", key, value),
+                entry: Some((key.to_string(), value.trim().to_string())),
+            });
+        }
+    }
+
+    /// All keys with their effective values.
+    pub fn entries(&self) -> BTreeMap<&str, &str> {
+        self.lines
+            .iter()
+            .filter_map(|l| l.entry.as_ref())
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect()
+    }
+
+    fn text(&self, key: &str) -> String {
+        self.get(key).unwrap_or_default().to_string()
+    }
+
+    pub fn name(&self) -> String {
+        self.text("name")
+    }
+
+    pub fn version(&self) -> String {
+        self.text("version")
+    }
+
+    pub fn author(&self) -> String {
+        self.text("author")
+    }
+
+    pub fn description(&self) -> String {
+        self.text("description")
+    }
+
+    pub fn version_code(&self) -> Option<i64> {
+        self.get("versionCode")?.parse().ok()
+    }
+
+    pub fn min_api(&self) -> Option<u32> {
+        self.get("minApi")?.parse().ok()
+    }
+
+    pub fn max_api(&self) -> Option<u32> {
+        self.get("maxApi")?.parse().ok()
+    }
+
+    /// `ro.product.device` values the module is made for; empty means any.
+    pub fn devices(&self) -> Vec<String> {
+        split_ids(self.get("devices").unwrap_or_default())
+    }
+
+    /// ABIs the module ships binaries for; empty means any.
+    pub fn abis(&self) -> Vec<String> {
+        split_ids(self.get("abis").unwrap_or_default())
+    }
+
+    pub fn requires(&self) -> Vec<String> {
+        split_ids(self.get("requires").unwrap_or_default())
+    }
+
+    pub fn conflicts(&self) -> Vec<String> {
+        split_ids(self.get("conflicts").unwrap_or_default())
+    }
+
+    pub fn replaces(&self) -> Vec<String> {
+        split_ids(self.get("replaces").unwrap_or_default())
+    }
+}
+
+/// Missing or unreadable files yield an empty prop.
+impl From<&Path> for ModuleProp {
+    fn from(path: &Path) -> Self {
+        Self::load(path).unwrap_or_default()
+    }
+}
+
+impl fmt::Display for ModuleProp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, line) in self.lines.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+
+            write!(f, "{}", line.raw)?;
+        }
+
+        if self.trailing_newline {
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Serialize for ModuleProp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.entries().serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str =
+        "# comment\nid=demo\nname = Demo Module \r\nversionCode=42\njunk line\n\ndescription=a=b\n";
+
+    #[test]
+    fn round_trip_is_exact() {
+        for content in [SAMPLE, "", "id=x", "id=x\r\n", "\n\n", "# only\n"] {
+            assert_eq!(ModuleProp::parse(content).to_string(), content);
+        }
+    }
+
+    #[test]
+    fn values_are_trimmed_and_split_on_the_first_equals() {
+        let prop = ModuleProp::parse(SAMPLE);
+
+        assert_eq!(prop.name(), "Demo Module");
+
+        assert_eq!(prop.description(), "a=b");
+
+        assert_eq!(prop.version_code(), Some(42));
+
+        assert_eq!(prop.get("junk line"), None);
+    }
+
+    #[test]
+    fn later_lines_win() {
+        let prop = ModuleProp::parse("name=first\nname=second\n");
+
+        assert_eq!(prop.name(), "second");
+
+        assert_eq!(prop.entries().get("name"), Some(&"second"));
+    }
+
+    #[test]
+    fn set_only_rewrites_touched_lines() {
+        let mut prop = ModuleProp::parse(SAMPLE);
+
+        prop.set("description", "new");
+
+        prop.set("author", "me");
+
+        assert_eq!(
+            prop.to_string(),
+            SAMPLE.replace("description=a=b", "description=new") + "author=me\n"
+        );
+    }
+
+    #[test]
+    fn id_lists_accept_commas_and_spaces() {
+        let prop = ModuleProp::parse("requires=a, b c,,d\nabis=arm64-v8a\n");
+
+        assert_eq!(prop.requires(), ["a", "b", "c", "d"]);
+
+        assert_eq!(prop.abis(), ["arm64-v8a"]);
+
+        assert!(prop.conflicts().is_empty());
+    }
+}
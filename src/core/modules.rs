@@ -2,9 +2,8 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 use std::{
-    collections::HashSet,
-    fs::{self, OpenOptions},
-    io::{BufRead, BufReader, Write},
+    collections::{BTreeMap, HashSet},
+    fs,
     os::unix::fs::{FileTypeExt, MetadataExt},
     path::{Path, PathBuf},
 };
@@ -16,76 +15,67 @@ use crate::{
     conf::config::Config,
     core::{
         inventory::{self, MountMode},
+        module_prop::ModuleProp,
         state::RuntimeState,
+        verify::{self, MountCheck},
     },
     defs,
 };
 
-#[derive(Default)]
-
-struct ModuleProp {
-    name: String,
-    version: String,
-    author: String,
-    description: String,
-}
-
-impl From<&Path> for ModuleProp {
-    fn from(path: &Path) -> Self {
-        let mut prop = ModuleProp::default();
-
-        if let Ok(file) = fs::File::open(path) {
-            for line in BufReader::new(file).lines().map_while(Result::ok) {
-                if let Some((k, v)) = line.split_once('=') {
-                    let val = v.trim().to_string();
-
-                    match k.trim() {
-                        "name" => prop.name = val,
-                        "version" => prop.version = val,
-                        "author" => prop.author = val,
-                        "description" => prop.description = val,
-                        _ => {}
-                    }
-                }
-            }
-        }
-
-        prop
-    }
-}
-
 #[derive(Serialize)]
-
 struct ModuleInfo {
     id: String,
     name: String,
     version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version_code: Option<i64>,
     author: String,
     description: String,
+    /// Every key of `module.prop`, including ones not interpreted here.
+    prop: ModuleProp,
     mode: String,
     is_mounted: bool,
+    priority: i32,
     rules: inventory::ModuleRules,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mount_check: Option<MountCheck>,
+    /// Why the module is left out of the mount plan.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    skip_reason: Option<String>,
 }
 
 impl ModuleInfo {
-    fn new(m: inventory::Module, mounted_set: &HashSet<&str>) -> Self {
-        let prop = ModuleProp::from(m.source_path.join("module.prop").as_path());
-
+    fn new(
+        m: inventory::Module,
+        mounted_set: &HashSet<&str>,
+        checks: &BTreeMap<String, MountCheck>,
+    ) -> Self {
         let mode_str = match m.rules.default_mode {
             MountMode::Overlay => "auto",
             MountMode::Magic => "magic",
             MountMode::Ignore => "ignore",
         };
 
+        let mount_check = checks.get(&m.id).cloned();
+
+        let gone = mount_check
+            .as_ref()
+            .is_some_and(|c| c.ok + c.shadowed == 0 && c.missing > 0);
+
         Self {
-            is_mounted: mounted_set.contains(m.id.as_str()),
+            is_mounted: mounted_set.contains(m.id.as_str()) && !gone,
+            mount_check,
             id: m.id,
-            name: prop.name,
-            version: prop.version,
-            author: prop.author,
-            description: prop.description,
+            name: m.prop.name(),
+            version: m.prop.version(),
+            version_code: m.prop.version_code(),
+            author: m.prop.author(),
+            description: m.prop.description(),
+            prop: m.prop,
             mode: mode_str.to_string(),
+            priority: m.priority,
             rules: m.rules,
+            skip_reason: None,
         }
     }
 }
@@ -129,7 +119,7 @@ impl ModuleFile {
 }
 
 pub fn print_list(config: &Config) -> Result<()> {
-    let modules = inventory::scan(&config.moduledir, config)?;
+    let scan = inventory::scan_report(&config.moduledir, config)?;
 
     let state = RuntimeState::load().unwrap_or_default();
 
@@ -140,11 +130,39 @@ pub fn print_list(config: &Config) -> Result<()> {
         .map(|s| s.as_str())
         .collect();
 
-    let infos: Vec<ModuleInfo> = modules
+    let checks = if state.mounts.is_empty() {
+        BTreeMap::new()
+    } else {
+        match verify::verify(&state, config) {
+            Ok(report) => report.modules,
+            Err(e) => {
+                log::warn!("Failed to verify mounts: {:#}", e);
+
+                BTreeMap::new()
+            }
+        }
+    };
+
+    let mut infos: Vec<ModuleInfo> = scan
+        .modules
         .into_iter()
-        .map(|m| ModuleInfo::new(m, &mounted_ids))
+        .map(|m| ModuleInfo::new(m, &mounted_ids, &checks))
         .collect();
 
+    for m in scan.dropped {
+        let reason = scan
+            .issues
+            .iter()
+            .find(|i| i.id == m.id)
+            .map(|i| i.reason.clone());
+
+        let mut info = ModuleInfo::new(m, &mounted_ids, &checks);
+
+        info.skip_reason = reason;
+
+        infos.push(info);
+    }
+
     println!("{}", serde_json::to_string(&infos)?);
 
     Ok(())
@@ -181,32 +199,18 @@ pub fn update_description(
     };
 
     let desc_text = format!(
-        "description=😋 运行中喵～ ({}) {} | Overlay: {} | Magic: {}{}",
+        "😋 运行中喵～ ({}) {} | Overlay: {} | Magic: {}{}",
         mode_str, status_emoji, overlay_count, magic_count, nuke_str
     );
 
-    let lines: Vec<String> = match fs::File::open(prop_path) {
-        Ok(file) => BufReader::new(file)
-            .lines()
-            .map_while(Result::ok)
-            .map(|line| {
-                if line.starts_with("description=") {
-                    desc_text.clone()
-                } else {
-                    line
-                }
-            })
-            .collect(),
+    let mut prop = match ModuleProp::load(prop_path) {
+        Ok(prop) => prop,
         Err(_) => return,
     };
 
-    if let Ok(mut file) = OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .open(prop_path)
-    {
-        for line in lines {
-            let _ = writeln!(file, "{}", line);
-        }
+    prop.set("description", &desc_text);
+
+    if let Err(e) = prop.save(prop_path) {
+        log::warn!("Failed to update module description: {:#}", e);
     }
 }
@@ -2,7 +2,7 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{HashMap, HashSet, hash_map::Entry},
     fs,
     path::{Path, PathBuf},
 };
@@ -14,29 +14,33 @@ use walkdir::WalkDir;
 
 use crate::{
     conf::config,
-    core::inventory::{Module, MountMode},
+    core::{
+        inventory::{Module, MountMode, UmountPolicy},
+        winnow,
+    },
     defs,
+    mount::magic::MagicScope,
 };
 
 #[derive(Debug, Clone)]
-
 pub struct OverlayOperation {
     pub partition_name: String,
     pub target: String,
     pub lowerdirs: Vec<PathBuf>,
+    pub umount: UmountPolicy,
 }
 
 #[derive(Debug, Default)]
-
 pub struct MountPlan {
     pub overlay_ops: Vec<OverlayOperation>,
     pub magic_module_paths: Vec<PathBuf>,
     pub overlay_module_ids: Vec<String>,
     pub magic_module_ids: Vec<String>,
+    pub precedence: Vec<String>,
+    pub magic_scopes: HashMap<PathBuf, MagicScope>,
 }
 
 #[derive(Debug, Clone, Serialize)]
-
 pub struct ConflictEntry {
     pub partition: String,
     pub relative_path: String,
@@ -44,7 +48,6 @@ pub struct ConflictEntry {
 }
 
 #[derive(Debug, Default)]
-
 pub struct ConflictReport {
     pub details: Vec<ConflictEntry>,
 }
@@ -161,10 +164,40 @@ impl MountPlan {
     }
 }
 
+/// Layers whose umount policy disagrees with the one their shared overlay
+/// ends up with. Served by magic mount instead, each of their paths can be
+/// registered on its own.
+fn umount_outliers(
+    part: &str,
+    layers: &[PathBuf],
+    scopes: &HashMap<PathBuf, MagicScope>,
+    default: bool,
+) -> Vec<PathBuf> {
+    let policy = |layer: &PathBuf| {
+        layer
+            .parent()
+            .and_then(|root| scopes.get(root))
+            .map(|scope| scope.rules.partition_umount(part))
+            .unwrap_or_default()
+    };
+
+    let shared = layers
+        .iter()
+        .fold(UmountPolicy::Default, |acc, l| acc.combine(policy(l)))
+        .should_register(default);
+
+    layers
+        .iter()
+        .filter(|l| policy(l).should_register(default) != shared)
+        .cloned()
+        .collect()
+}
+
 struct ModuleContribution {
     id: String,
     overlays: Vec<(String, PathBuf)>,
-    magic_path: Option<PathBuf>,
+    magic_roots: Vec<PathBuf>,
+    scopes: HashMap<PathBuf, MagicScope>,
 }
 
 pub fn generate(
@@ -176,7 +209,11 @@ pub fn generate(
 
     let mut target_partitions = defs::BUILTIN_PARTITIONS.to_vec();
 
-    target_partitions.extend(config.partitions.iter().map(|s| s.as_str()));
+    for part in &config.partitions {
+        if !target_partitions.contains(&part.as_str()) {
+            target_partitions.push(part.as_str());
+        }
+    }
 
     let contributions: Vec<Option<ModuleContribution>> = modules
         .par_iter()
@@ -194,76 +231,147 @@ pub fn generate(
             let mut contrib = ModuleContribution {
                 id: module.id.clone(),
                 overlays: Vec::new(),
-                magic_path: None,
+                magic_roots: Vec::new(),
+                scopes: HashMap::new(),
             };
 
-            let mut has_any_action = false;
+            let probe = MagicScope::new(module.rules.clone());
 
-            if let Ok(entries) = fs::read_dir(&content_path) {
-                for entry in entries.flatten() {
-                    let path = entry.path();
+            for part in &target_partitions {
+                let layer = content_path.join(part);
 
-                    if !path.is_dir() {
-                        continue;
-                    }
+                if module.rules.is_overlay_capable(part) && layer.is_dir() && has_files(&layer) {
+                    let leftovers = module
+                        .rules
+                        .carved_paths(part)
+                        .into_iter()
+                        .any(|k| fs::symlink_metadata(content_path.join(k)).is_ok());
 
-                    let dir_name = entry.file_name().to_string_lossy().to_string();
+                    let scope = contrib
+                        .scopes
+                        .entry(content_path.clone())
+                        .or_insert_with(|| MagicScope::new(module.rules.clone()));
 
-                    if !target_partitions.contains(&dir_name.as_str()) {
-                        continue;
-                    }
+                    if leftovers {
+                        log::debug!(
+                            "{}/{} still carries carved sub-trees, demoting to magic mount",
+                            module.id,
+                            part
+                        );
 
-                    if !has_files(&path) {
-                        continue;
-                    }
+                        scope.partitions.insert(part.to_string());
 
-                    let mode = module.rules.get_mode(&dir_name);
+                        if !contrib.magic_roots.contains(&content_path) {
+                            contrib.magic_roots.push(content_path.clone());
+                        }
+                    } else {
+                        contrib.overlays.push((part.to_string(), layer));
+                    }
+                }
 
-                    match mode {
-                        MountMode::Overlay => {
-                            contrib.overlays.push((dir_name, path));
+                let source = module.source_path.join(part);
 
-                            has_any_action = true;
-                        }
-                        MountMode::Magic => {
-                            contrib.magic_path = Some(content_path.clone());
+                if probe.touches(part) && source.is_dir() && has_files(&source) {
+                    contrib
+                        .scopes
+                        .entry(module.source_path.clone())
+                        .or_insert_with(|| MagicScope::new(module.rules.clone()));
 
-                            has_any_action = true;
-                        }
-                        MountMode::Ignore => {
-                            log::debug!("Ignoring {}/{} per rule", module.id, dir_name);
-                        }
+                    if !contrib.magic_roots.contains(&module.source_path) {
+                        contrib.magic_roots.push(module.source_path.clone());
                     }
+                } else if module.rules.get_mode(part) == MountMode::Ignore && source.is_dir() {
+                    log::debug!("Ignoring {}/{} per rule", module.id, part);
                 }
             }
 
-            if has_any_action { Some(contrib) } else { None }
+            if contrib.overlays.is_empty() && contrib.magic_roots.is_empty() {
+                None
+            } else {
+                Some(contrib)
+            }
         })
         .collect();
 
     let mut overlay_groups: HashMap<String, Vec<PathBuf>> = HashMap::new();
 
-    let mut magic_paths = HashSet::new();
+    let mut magic_paths: Vec<PathBuf> = Vec::new();
 
     let mut overlay_ids = HashSet::new();
 
     let mut magic_ids = HashSet::new();
 
+    let mut layer_owners: HashMap<PathBuf, String> = HashMap::new();
+
     for contrib in contributions.into_iter().flatten() {
-        if let Some(path) = contrib.magic_path {
-            magic_paths.insert(path);
+        for path in contrib.magic_roots {
+            if !magic_paths.contains(&path) {
+                magic_paths.push(path);
+            }
 
             magic_ids.insert(contrib.id.clone());
         }
 
         for (part, path) in contrib.overlays {
+            layer_owners.insert(path.clone(), contrib.id.clone());
+
             overlay_groups.entry(part).or_default().push(path);
 
             overlay_ids.insert(contrib.id.clone());
         }
+
+        for (root, scope) in contrib.scopes {
+            match plan.magic_scopes.entry(root) {
+                Entry::Vacant(v) => {
+                    v.insert(scope);
+                }
+                Entry::Occupied(mut o) => o.get_mut().partitions.extend(scope.partitions),
+            }
+        }
     }
 
-    for (part, layers) in overlay_groups {
+    for (part, layers) in overlay_groups.iter_mut() {
+        let outliers = umount_outliers(part, layers, &plan.magic_scopes, !config.disable_umount);
+
+        for layer in outliers {
+            let (Some(root), Some(id)) = (layer.parent(), layer_owners.get(&layer)) else {
+                continue;
+            };
+
+            log::info!(
+                "{}/{} has a different umount policy than the rest of the overlay, using magic mount",
+                id,
+                part
+            );
+
+            if let Some(scope) = plan.magic_scopes.get_mut(root) {
+                scope.partitions.insert(part.clone());
+            }
+
+            if !magic_paths.iter().any(|p| p == root) {
+                magic_paths.push(root.to_path_buf());
+            }
+
+            magic_ids.insert(id.clone());
+
+            layers.retain(|l| *l != layer);
+        }
+    }
+
+    overlay_ids.retain(|id| {
+        overlay_groups
+            .values()
+            .flatten()
+            .any(|l| layer_owners.get(l) == Some(id))
+    });
+
+    for (part, mut layers) in overlay_groups {
+        if layers.is_empty() {
+            continue;
+        }
+
+        winnow::thresh_layers(&part, &mut layers, &config.winnowing);
+
         let initial_target_path = format!("/{}", part);
 
         let target_path_obj = Path::new(&initial_target_path);
@@ -293,14 +401,24 @@ pub fn generate(
             continue;
         }
 
+        let umount = layers
+            .iter()
+            .filter_map(|layer| layer.parent().and_then(|root| plan.magic_scopes.get(root)))
+            .fold(UmountPolicy::Default, |acc, scope| {
+                acc.combine(scope.rules.partition_umount(&part))
+            });
+
         plan.overlay_ops.push(OverlayOperation {
             partition_name: part,
             target: resolved_target.to_string_lossy().to_string(),
             lowerdirs: layers,
+            umount,
         });
     }
 
-    plan.magic_module_paths = magic_paths.into_iter().collect();
+    plan.magic_module_paths = magic_paths;
+
+    plan.precedence = modules.iter().map(|m| m.id.clone()).collect();
 
     plan.overlay_module_ids = overlay_ids.into_iter().collect();
 
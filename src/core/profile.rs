@@ -0,0 +1,180 @@
+// Copyright 2025 Meta-Hybrid Mount Authors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::path::Path;
+
+use anyhow::{Result, bail};
+
+use crate::{
+    conf::{
+        config::{Config, MountProfile},
+        config_file::ConfigFile,
+    },
+    core::{graft, granary, state::RuntimeState},
+    defs,
+};
+
+/// What switching profiles changed, for reporting and hot-apply.
+#[derive(Debug, Default)]
+pub struct SwitchReport {
+    pub enabled: Vec<String>,
+    pub disabled: Vec<String>,
+    /// Profile modules that are not installed.
+    pub missing: Vec<String>,
+    pub partitions_changed: bool,
+}
+
+impl SwitchReport {
+    /// Modules whose mounts differ between the two profiles.
+    fn touched(&self, old: Option<&MountProfile>, new: Option<&MountProfile>) -> Vec<String> {
+        let mut ids: Vec<String> = self
+            .enabled
+            .iter()
+            .chain(self.disabled.iter())
+            .chain(old.into_iter().flat_map(|p| p.rules.keys()))
+            .chain(new.into_iter().flat_map(|p| p.rules.keys()))
+            .cloned()
+            .collect();
+
+        ids.sort();
+
+        ids.dedup();
+
+        ids
+    }
+}
+
+/// Flips the `disable` flags so only the profile's modules stay enabled.
+fn apply_module_set(
+    moduledir: &Path,
+    profile: &MountProfile,
+    report: &mut SwitchReport,
+) -> Result<()> {
+    if profile.modules.is_empty() {
+        return Ok(());
+    }
+
+    let installed = granary::capture_modules(moduledir)?;
+
+    for module in &installed {
+        let enabled = profile.modules.contains(&module.id);
+
+        if granary::set_flag(
+            &moduledir.join(&module.id),
+            defs::DISABLE_FILE_NAME,
+            !enabled,
+        )? {
+            if enabled {
+                report.enabled.push(module.id.clone());
+            } else {
+                report.disabled.push(module.id.clone());
+            }
+        }
+    }
+
+    report.missing = profile
+        .modules
+        .iter()
+        .filter(|id| !installed.iter().any(|m| &m.id == *id))
+        .cloned()
+        .collect();
+
+    Ok(())
+}
+
+/// Makes `name` the active profile (`None` clears it) and saves the config
+/// to `config_path`. The change takes effect on the next boot; with
+/// `hot_apply` the touched modules are remounted right away.
+pub fn switch(
+    config: &Config,
+    name: Option<&str>,
+    config_path: &Path,
+    hot_apply: bool,
+) -> Result<SwitchReport> {
+    let new = match name {
+        Some(name) => match config.profiles.get(name) {
+            Some(profile) => Some(profile),
+            None => bail!("Unknown profile '{}'", name),
+        },
+        None => None,
+    };
+
+    let old = config.active_profile();
+
+    if let Err(e) = granary::create_silo(
+        config,
+        "Pre-Profile",
+        &format!("Before switching to profile {}", name.unwrap_or("(none)")),
+    ) {
+        log::warn!("Failed to create Granary backup: {}", e);
+    }
+
+    let mut report = SwitchReport::default();
+
+    if let Some(profile) = new {
+        apply_module_set(&config.moduledir, profile, &mut report)?;
+    }
+
+    let mut updated = config.clone();
+
+    updated.profile = name.map(String::from);
+
+    let mut file = ConfigFile::load(config_path)?;
+
+    match name {
+        Some(name) => file.set("profile", name)?,
+        None => file.remove("profile"),
+    }
+
+    file.save(config_path)?;
+
+    let mut effective = config.clone();
+
+    effective.apply_profile();
+
+    let before = effective.partitions;
+
+    updated.apply_profile();
+
+    report.partitions_changed = before != updated.partitions;
+
+    log::info!(
+        ">> Profile: {} (enabled {:?}, disabled {:?})",
+        name.unwrap_or("(none)"),
+        report.enabled,
+        report.disabled
+    );
+
+    if !hot_apply {
+        return Ok(report);
+    }
+
+    if report.partitions_changed {
+        log::warn!(">> Partition list changed; a reboot is needed for it to take effect.");
+    }
+
+    let mut failed = Vec::new();
+
+    for id in report.touched(old, new) {
+        if let Err(e) = graft::apply_module(&updated, &id) {
+            log::error!("Failed to hot-apply {}: {:#}", id, e);
+
+            failed.push(id);
+        }
+    }
+
+    if let Ok(mut state) = RuntimeState::load() {
+        state.profile = updated.profile.clone();
+
+        state.save()?;
+    }
+
+    if !failed.is_empty() {
+        bail!(
+            "Profile saved, but {} could not be applied live; reboot to finish",
+            failed.join(", ")
+        );
+    }
+
+    Ok(report)
+}
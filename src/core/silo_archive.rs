@@ -0,0 +1,227 @@
+// Copyright 2025 Meta-Hybrid Mount Authors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    core::{
+        granary::{self, Silo},
+        inventory::ModuleRules,
+    },
+    utils,
+};
+
+const ARCHIVE_FORMAT: &str = "meta-hybrid-silo";
+
+const ARCHIVE_VERSION: u32 = 1;
+
+/// Self-contained, portable form of a silo.
+///
+/// The checksum covers the canonical JSON of `silo`, which is kept as a raw
+/// value so fields added by newer versions survive the round trip.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SiloArchive {
+    pub format: String,
+    pub version: u32,
+    pub exported_at: u64,
+    #[serde(default)]
+    pub exporter: String,
+    #[serde(default)]
+    pub device: Option<String>,
+    pub checksum: String,
+    pub silo: Value,
+}
+
+fn silo_checksum(silo: &Value) -> Result<String> {
+    Ok(utils::checksum(&serde_json::to_vec(silo)?))
+}
+
+pub fn export(silo: &Silo, file: &Path) -> Result<()> {
+    // Round trip through `Value` so map keys come out sorted.
+    let silo = serde_json::to_value(silo)?;
+
+    let archive = SiloArchive {
+        format: ARCHIVE_FORMAT.to_string(),
+        version: ARCHIVE_VERSION,
+        exported_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        exporter: format!("meta-hybrid {}", env!("CARGO_PKG_VERSION")),
+        device: utils::getprop("ro.product.device"),
+        checksum: silo_checksum(&silo)?,
+        silo,
+    };
+
+    let json = serde_json::to_string_pretty(&archive)?;
+
+    fs::write(file, json).with_context(|| format!("Failed to write {}", file.display()))
+}
+
+/// Rewrites the leading partition of `path`. `None` means the partition is
+/// dropped.
+fn remap_path(path: &str, remap: &HashMap<String, String>) -> Option<String> {
+    let absolute = path.starts_with('/');
+
+    let trimmed = path.trim_start_matches('/');
+
+    let (head, rest) = match trimmed.split_once('/') {
+        Some((head, rest)) => (head, Some(rest)),
+        None => (trimmed, None),
+    };
+
+    let head = match remap.get(head) {
+        Some(new) if new.is_empty() => return None,
+        Some(new) => new.as_str(),
+        None => head,
+    };
+
+    let mut out = String::new();
+
+    if absolute {
+        out.push('/');
+    }
+
+    out.push_str(head);
+
+    if let Some(rest) = rest {
+        out.push('/');
+
+        out.push_str(rest);
+    }
+
+    Some(out)
+}
+
+fn remap_partitions(silo: &mut Silo, remap: &HashMap<String, String>) -> Result<()> {
+    // Restore prefers the raw file, which would bring the old partitions back.
+    silo.raw_config = None;
+
+    let config = &mut silo.config_snapshot;
+
+    config.partitions = config
+        .partitions
+        .iter()
+        .filter_map(|p| remap_path(p, remap))
+        .collect();
+
+    config.winnowing.rules = config
+        .winnowing
+        .rules
+        .drain()
+        .filter_map(|(path, id)| remap_path(&path, remap).map(|p| (p, id)))
+        .collect();
+
+    if let Some(rules) = &mut silo.rules {
+        for content in rules.values_mut() {
+            let mut parsed: ModuleRules = serde_json::from_str(content)?;
+
+            parsed.paths = parsed
+                .paths
+                .drain()
+                .filter_map(|(path, mode)| remap_path(&path, remap).map(|p| (p, mode)))
+                .collect();
+
+            parsed.umount_paths = parsed
+                .umount_paths
+                .drain()
+                .filter_map(|(path, policy)| remap_path(&path, remap).map(|p| (p, policy)))
+                .collect();
+
+            *content = serde_json::to_string_pretty(&parsed)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn validate(silo: &Silo) -> Result<()> {
+    if silo.id.is_empty() || silo.id.contains('/') || silo.id.starts_with('.') {
+        bail!("Invalid silo id '{}'", silo.id);
+    }
+
+    for module in silo.modules.iter().flatten() {
+        utils::validate_module_id(&module.id)?;
+    }
+
+    let rules: &BTreeMap<String, String> = match &silo.rules {
+        Some(rules) => rules,
+        None => return Ok(()),
+    };
+
+    for (name, content) in rules {
+        let Some(id) = name.strip_suffix(".json") else {
+            bail!("Unexpected rule file '{}'", name);
+        };
+
+        utils::validate_module_id(id)?;
+
+        serde_json::from_str::<ModuleRules>(content)
+            .with_context(|| format!("Invalid rules for module '{}'", id))?;
+    }
+
+    Ok(())
+}
+
+/// Validates an archive and stores its silo in the granary, remapping
+/// partitions (`old` -> `new`, empty `new` drops it) on the way.
+pub fn import(file: &Path, remap: &HashMap<String, String>) -> Result<String> {
+    let content =
+        fs::read_to_string(file).with_context(|| format!("Failed to read {}", file.display()))?;
+
+    let archive: SiloArchive = serde_json::from_str(&content).context("Not a silo archive")?;
+
+    if archive.format != ARCHIVE_FORMAT {
+        bail!("Unknown archive format '{}'", archive.format);
+    }
+
+    if archive.version > ARCHIVE_VERSION {
+        bail!(
+            "Archive version {} is newer than supported ({})",
+            archive.version,
+            ARCHIVE_VERSION
+        );
+    }
+
+    let checksum = silo_checksum(&archive.silo)?;
+
+    if checksum != archive.checksum {
+        bail!(
+            "Checksum mismatch (expected {}, got {}); archive is corrupted",
+            archive.checksum,
+            checksum
+        );
+    }
+
+    let mut silo: Silo =
+        serde_json::from_value(archive.silo).context("Archive holds an invalid silo")?;
+
+    validate(&silo)?;
+
+    if granary::silo_exists(&silo.id) {
+        bail!("Silo {} already exists in the granary", silo.id);
+    }
+
+    if !remap.is_empty() {
+        remap_partitions(&mut silo, remap)?;
+    }
+
+    // Whether it booted elsewhere says nothing about this device.
+    silo.known_good = false;
+
+    silo.imported = true;
+
+    if let Some(device) = &archive.device {
+        log::info!("Importing silo {} exported on {}", silo.id, device);
+    }
+
+    granary::save_silo(&silo)?;
+
+    Ok(silo.id)
+}
@@ -0,0 +1,228 @@
+// Copyright 2025 Meta-Hybrid Mount Authors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::fmt;
+
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::{
+    conf::config::Config,
+    core::granary::{self, ModuleEntry, Silo},
+};
+
+/// Pseudo silo id standing for the live configuration.
+pub const CURRENT: &str = "current";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldChange {
+    pub key: String,
+    pub from: Option<Value>,
+    pub to: Option<Value>,
+}
+
+/// Differences between two silos, section by section. Sections that one of
+/// the silos did not capture are `None`.
+#[derive(Debug, Serialize)]
+pub struct SiloDiff {
+    pub from: String,
+    pub to: String,
+    pub config: Vec<FieldChange>,
+    pub winnowing: Vec<FieldChange>,
+    pub rules: Option<Vec<FieldChange>>,
+    pub modules: Option<Vec<FieldChange>>,
+}
+
+impl SiloDiff {
+    pub fn is_empty(&self) -> bool {
+        self.config.is_empty()
+            && self.winnowing.is_empty()
+            && self.rules.as_ref().is_none_or(Vec::is_empty)
+            && self.modules.as_ref().is_none_or(Vec::is_empty)
+    }
+}
+
+fn join_key(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+/// Walks two JSON trees and records every leaf that differs. Arrays are
+/// compared as a whole.
+fn diff_values(prefix: &str, from: Option<&Value>, to: Option<&Value>, out: &mut Vec<FieldChange>) {
+    match (from, to) {
+        (Some(Value::Object(a)), Some(Value::Object(b))) => {
+            let mut keys: Vec<&String> = a.keys().chain(b.keys()).collect();
+
+            keys.sort();
+
+            keys.dedup();
+
+            for key in keys {
+                diff_values(&join_key(prefix, key), a.get(key), b.get(key), out);
+            }
+        }
+        (a, b) if a != b => out.push(FieldChange {
+            key: prefix.to_string(),
+            from: a.cloned(),
+            to: b.cloned(),
+        }),
+        _ => {}
+    }
+}
+
+fn config_value(silo: &Silo) -> Result<(Value, Value)> {
+    let mut config = serde_json::to_value(&silo.config_snapshot)?;
+
+    let winnowing = config
+        .as_object_mut()
+        .and_then(|map| map.remove("winnowing"))
+        .unwrap_or(Value::Null);
+
+    Ok((config, winnowing))
+}
+
+fn rules_value(silo: &Silo) -> Option<Value> {
+    let rules = silo.rules.as_ref()?;
+
+    let map: Map<String, Value> = rules
+        .iter()
+        .map(|(name, content)| {
+            let id = name.strip_suffix(".json").unwrap_or(name).to_string();
+
+            let value = serde_json::from_str(content).unwrap_or(Value::String(content.clone()));
+
+            (id, value)
+        })
+        .collect();
+
+    Some(Value::Object(map))
+}
+
+fn modules_value(silo: &Silo) -> Option<Value> {
+    let modules: &Vec<ModuleEntry> = silo.modules.as_ref()?;
+
+    let map: Map<String, Value> = modules
+        .iter()
+        .map(|m| {
+            let mut entry = serde_json::to_value(m).unwrap_or_default();
+
+            if let Some(obj) = entry.as_object_mut() {
+                obj.remove("id");
+            }
+
+            (m.id.clone(), entry)
+        })
+        .collect();
+
+    Some(Value::Object(map))
+}
+
+fn diff_section(from: Option<Value>, to: Option<Value>) -> Option<Vec<FieldChange>> {
+    let (from, to) = (from?, to?);
+
+    let mut out = Vec::new();
+
+    diff_values("", Some(&from), Some(&to), &mut out);
+
+    Some(out)
+}
+
+pub fn diff(from: &Silo, to: &Silo) -> Result<SiloDiff> {
+    let (from_config, from_winnowing) = config_value(from)?;
+
+    let (to_config, to_winnowing) = config_value(to)?;
+
+    let mut config = Vec::new();
+
+    diff_values("", Some(&from_config), Some(&to_config), &mut config);
+
+    let mut winnowing = Vec::new();
+
+    diff_values(
+        "",
+        Some(&from_winnowing),
+        Some(&to_winnowing),
+        &mut winnowing,
+    );
+
+    Ok(SiloDiff {
+        from: from.id.clone(),
+        to: to.id.clone(),
+        config,
+        winnowing,
+        rules: diff_section(rules_value(from), rules_value(to)),
+        modules: diff_section(modules_value(from), modules_value(to)),
+    })
+}
+
+/// Loads a silo by id, or captures the live setup for [`CURRENT`].
+pub fn resolve(id: &str, config: &Config) -> Result<Silo> {
+    if id == CURRENT {
+        return granary::snapshot(config, CURRENT, "Current", "Live configuration");
+    }
+
+    granary::load_silo(id)
+}
+
+fn fmt_value(value: &Option<Value>) -> String {
+    match value {
+        Some(Value::String(s)) => format!("\"{}\"", s),
+        Some(v) => v.to_string(),
+        None => "-".to_string(),
+    }
+}
+
+fn fmt_section(
+    f: &mut fmt::Formatter<'_>,
+    title: &str,
+    changes: Option<&Vec<FieldChange>>,
+) -> fmt::Result {
+    let Some(changes) = changes else {
+        return writeln!(f, "[{}] not captured by both silos", title);
+    };
+
+    if changes.is_empty() {
+        return Ok(());
+    }
+
+    writeln!(f, "[{}]", title)?;
+
+    for change in changes {
+        match (&change.from, &change.to) {
+            (None, to) => writeln!(f, "  + {}: {}", change.key, fmt_value(to))?,
+            (from, None) => writeln!(f, "  - {}: {}", change.key, fmt_value(from))?,
+            (from, to) => writeln!(
+                f,
+                "  ~ {}: {} -> {}",
+                change.key,
+                fmt_value(from),
+                fmt_value(to)
+            )?,
+        }
+    }
+
+    Ok(())
+}
+
+impl fmt::Display for SiloDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Diff {} -> {}", self.from, self.to)?;
+
+        if self.is_empty() {
+            writeln!(f, "No differences.")?;
+        }
+
+        fmt_section(f, "config", Some(&self.config))?;
+
+        fmt_section(f, "winnowing", Some(&self.winnowing))?;
+
+        fmt_section(f, "rules", self.rules.as_ref())?;
+
+        fmt_section(f, "modules", self.modules.as_ref())
+    }
+}
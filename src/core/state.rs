@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 use std::{
+    collections::BTreeMap,
     fs,
     path::PathBuf,
     time::{SystemTime, UNIX_EPOCH},
@@ -10,10 +11,13 @@ use std::{
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
-use crate::defs;
+use crate::{
+    core::{executor::ExecutionOutcome, sync::SyncStats},
+    defs,
+    mount::journal::MountRecord,
+};
 
 #[derive(Debug, Serialize, Deserialize, Default)]
-
 pub struct RuntimeState {
     pub timestamp: u64,
     pub pid: u32,
@@ -32,11 +36,22 @@ pub struct RuntimeState {
     pub storage_percent: u8,
     #[serde(default)]
     pub zygisksu_enforce: bool,
+    #[serde(default)]
+    pub sync_stats: BTreeMap<String, SyncStats>,
+    #[serde(default)]
+    pub mounts: Vec<MountRecord>,
+    #[serde(default)]
+    pub outcome: ExecutionOutcome,
+    /// Mount profile the session was built from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// Paths registered for KernelSU's try-umount.
+    #[serde(default)]
+    pub umount_paths: Vec<String>,
 }
 
 impl RuntimeState {
     #[allow(clippy::too_many_arguments)]
-
     pub fn new(
         storage_mode: String,
         mount_point: PathBuf,
@@ -45,6 +60,9 @@ impl RuntimeState {
         nuke_active: bool,
         active_mounts: Vec<String>,
         storage_info: (u64, u64, u8),
+        sync_stats: BTreeMap<String, SyncStats>,
+        mounts: Vec<MountRecord>,
+        outcome: ExecutionOutcome,
     ) -> Self {
         let start = SystemTime::now();
 
@@ -70,6 +88,11 @@ impl RuntimeState {
             storage_used: storage_info.1,
             storage_percent: storage_info.2,
             zygisksu_enforce,
+            sync_stats,
+            mounts,
+            outcome,
+            profile: None,
+            umount_paths: crate::try_umount::registered(),
         }
     }
 
@@ -81,6 +104,14 @@ impl RuntimeState {
         Ok(())
     }
 
+    pub fn clear() -> Result<()> {
+        if std::path::Path::new(defs::STATE_FILE).exists() {
+            fs::remove_file(defs::STATE_FILE)?;
+        }
+
+        Ok(())
+    }
+
     pub fn load() -> Result<Self> {
         if !std::path::Path::new(defs::STATE_FILE).exists() {
             return Ok(Self::default());
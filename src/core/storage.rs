@@ -16,7 +16,12 @@ use rustix::{
 use serde::Serialize;
 use walkdir::WalkDir;
 
-use crate::{core::state::RuntimeState, defs, utils};
+use crate::{
+    core::state::RuntimeState,
+    defs,
+    mount::journal::{self, MountRecord, MountRole},
+    utils,
+};
 
 #[cfg(any(target_os = "linux", target_os = "android"))]
 use crate::try_umount::send_unmountable;
@@ -45,9 +50,21 @@ impl StorageHandle {
             unmount(&self.mount_point, UnmountFlags::DETACH)
                 .context("Failed to unmount staging tmpfs")?;
 
+            journal::revert(&self.mount_point);
+
             utils::mount_erofs_image(image_path, &self.mount_point)
                 .context("Failed to mount finalized EROFS image")?;
 
+            journal::record(
+                MountRecord::new(
+                    &self.mount_point,
+                    "erofs",
+                    &image_path.to_string_lossy(),
+                    MountRole::Storage,
+                )
+                .partition("storage"),
+            );
+
             #[cfg(any(target_os = "linux", target_os = "android"))]
             if !disable_umount {
                 let _ = send_unmountable(&self.mount_point);
@@ -61,7 +78,6 @@ impl StorageHandle {
 }
 
 #[derive(Serialize)]
-
 struct StorageStatus {
     #[serde(rename = "type")]
     mode: String,
@@ -79,11 +95,7 @@ pub fn get_usage(path: &Path) -> (u64, u64, u8) {
 
         let used = total - free;
 
-        let percent = if total > 0 {
-            (used * 100 / total) as u8
-        } else {
-            0
-        };
+        let percent = (used * 100).checked_div(total).unwrap_or(0) as u8;
 
         (total, used, percent)
     } else {
@@ -119,6 +131,12 @@ pub fn setup(
 
         utils::mount_tmpfs(mnt_base, mount_source)?;
 
+        journal::record(
+            MountRecord::new(mnt_base, "tmpfs", mount_source, MountRole::Storage)
+                .partition("storage")
+                .options("mode=0755"),
+        );
+
         try_hide(mnt_base);
 
         if img_path.exists() {
@@ -133,6 +151,12 @@ pub fn setup(
     }
 
     if !force_ext4 && try_setup_tmpfs(mnt_base, mount_source)? {
+        journal::record(
+            MountRecord::new(mnt_base, "tmpfs", mount_source, MountRole::Storage)
+                .partition("storage")
+                .options("mode=0755"),
+        );
+
         try_hide(mnt_base);
 
         if img_path.exists()
@@ -156,6 +180,16 @@ pub fn setup(
 
     let handle = setup_ext4_image(mnt_base, img_path, moduledir)?;
 
+    journal::record(
+        MountRecord::new(
+            mnt_base,
+            "ext4",
+            &img_path.to_string_lossy(),
+            MountRole::Storage,
+        )
+        .partition("storage"),
+    );
+
     try_hide(mnt_base);
 
     Ok(handle)
@@ -243,7 +277,6 @@ fn create_image(path: &Path, moduledir: &Path) -> Result<()> {
 }
 
 #[allow(dead_code)]
-
 pub fn finalize_storage_permissions(target: &Path) {
     if let Err(e) = rustix::fs::chmod(target, Mode::from(0o755)) {
         log::warn!("Failed to chmod storage root: {}", e);
@@ -316,9 +349,7 @@ pub fn print_status() -> Result<()> {
 
         used = total - free;
 
-        if total > 0 {
-            percent = (used * 100 / total) as u8;
-        }
+        percent = (used * 100).checked_div(total).unwrap_or(0) as u8;
     }
 
     let status = StorageStatus {
@@ -1,56 +1,446 @@
 // Copyright 2025 Meta-Hybrid Mount Authors
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use std::{collections::HashSet, fs, path::Path};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    io::Read,
+    os::unix::fs::MetadataExt,
+    path::Path,
+};
 
 use anyhow::Result;
 use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
 
 use crate::{
-    core::inventory::{Module, MountMode},
+    conf::config::Config,
+    core::inventory::{Module, ModuleRules},
     defs, utils,
 };
 
-pub fn perform_sync(modules: &[Module], target_base: &Path) -> Result<()> {
+const MANIFEST_FILE: &str = ".hybrid_manifest.json";
+
+const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct ManifestEntry {
+    pub size: u64,
+    pub mtime: i64,
+    pub mtime_nsec: i64,
+    pub mode: u32,
+    #[serde(default)]
+    pub uid: u32,
+    #[serde(default)]
+    pub gid: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+/// Snapshot of a module source tree taken when it was last synced.
+/// It is stored at the root of the synced copy and compared against the
+/// source on every boot.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Default)]
+pub struct SyncManifest {
+    pub version: u32,
+    #[serde(default)]
+    pub carving: Vec<String>,
+    /// `versionCode` of the module when it was synced. A change means the
+    /// module was updated and is copied from scratch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version_code: Option<i64>,
+    #[serde(default)]
+    pub entries: BTreeMap<String, ManifestEntry>,
+}
+
+impl SyncManifest {
+    pub fn scan(src: &Path, carving: &[String], with_hash: bool) -> Self {
+        let mut entries = BTreeMap::new();
+
+        let walker = WalkDir::new(src)
+            .min_depth(1)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_entry(|e| {
+                e.path()
+                    .strip_prefix(src)
+                    .map(|rel| !carving.iter().any(|c| rel.starts_with(c)))
+                    .unwrap_or(true)
+            });
+
+        for entry in walker.flatten() {
+            let Ok(rel) = entry.path().strip_prefix(src) else {
+                continue;
+            };
+
+            let Ok(meta) = entry.path().symlink_metadata() else {
+                continue;
+            };
+
+            let hash = if with_hash && meta.is_file() {
+                hash_file(entry.path()).ok()
+            } else {
+                None
+            };
+
+            entries.insert(
+                rel.to_string_lossy().to_string(),
+                ManifestEntry {
+                    size: meta.len(),
+                    mtime: meta.mtime(),
+                    mtime_nsec: meta.mtime_nsec(),
+                    mode: meta.mode(),
+                    uid: meta.uid(),
+                    gid: meta.gid(),
+                    hash,
+                },
+            );
+        }
+
+        Self {
+            version: MANIFEST_VERSION,
+            carving: carving.to_vec(),
+            version_code: None,
+            entries,
+        }
+    }
+
+    pub fn load(module_root: &Path) -> Option<Self> {
+        let content = fs::read_to_string(module_root.join(MANIFEST_FILE)).ok()?;
+
+        serde_json::from_str(&content).ok()
+    }
+
+    pub fn save(&self, module_root: &Path) -> Result<()> {
+        let json = serde_json::to_string(self)?;
+
+        fs::write(module_root.join(MANIFEST_FILE), json)?;
+
+        Ok(())
+    }
+}
+
+/// FNV-1a over the file content; only used to detect edits that keep size
+/// and mtime intact, not for integrity.
+fn hash_file(path: &Path) -> std::io::Result<String> {
+    let mut file = fs::File::open(path)?;
+
+    let mut buf = [0u8; 64 * 1024];
+
+    let mut hash = utils::FNV_OFFSET;
+
+    loop {
+        let n = file.read(&mut buf)?;
+
+        if n == 0 {
+            break;
+        }
+
+        hash = utils::fnv1a(hash, &buf[..n]);
+    }
+
+    Ok(format!("{:016x}", hash))
+}
+
+/// Per-module outcome of a sync pass.
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct SyncStats {
+    pub full_copy: bool,
+    pub added_files: u64,
+    pub added_bytes: u64,
+    pub updated_files: u64,
+    pub updated_bytes: u64,
+    pub removed_files: u64,
+    pub removed_bytes: u64,
+}
+
+pub fn perform_sync(
+    modules: &[Module],
+    target_base: &Path,
+    config: &Config,
+) -> Result<BTreeMap<String, SyncStats>> {
     log::info!("Starting smart module sync to {}", target_base.display());
 
     prune_orphaned_modules(modules, target_base)?;
 
-    modules.par_iter().for_each(|module| {
-        if matches!(module.rules.default_mode, MountMode::Magic) {
-            log::debug!("Skipping sync for Magic Mount module: {}", module.id);
+    let results: Vec<(String, SyncStats)> = modules
+        .par_iter()
+        .filter_map(|module| match sync_module(module, target_base, config) {
+            Ok(stats) => stats.map(|stats| (module.id.clone(), stats)),
+            Err(e) => {
+                log::error!("Failed to sync module {}: {}", module.id, e);
 
-            return;
-        }
+                None
+            }
+        })
+        .collect();
+
+    Ok(results.into_iter().collect())
+}
 
-        let dst = target_base.join(&module.id);
+fn manifest_of(module: &Module, config: &Config) -> SyncManifest {
+    let carving = carving_of(&module.rules);
 
-        let has_content = defs::BUILTIN_PARTITIONS.iter().any(|p| {
-            let part_path = module.source_path.join(p);
+    let mut manifest = SyncManifest::scan(&module.source_path, &carving, config.checksum_sync);
 
-            part_path.exists() && has_files_recursive(&part_path)
-        });
+    manifest.version_code = module.prop.version_code();
 
-        if has_content && should_sync(&module.source_path, &dst) {
-            log::info!("Syncing module: {} (Updated/New)", module.id);
+    manifest
+}
+
+/// True when `target_base/<id>` matches the module source, so syncing it
+/// again would change nothing.
+pub fn is_current(module: &Module, target_base: &Path, config: &Config) -> bool {
+    SyncManifest::load(&target_base.join(&module.id))
+        .is_some_and(|previous| previous == manifest_of(module, config))
+}
+
+/// Brings `target_base/<id>` up to date with the module source. Returns
+/// `None` when the module needs no storage copy or is already current.
+pub fn sync_module(
+    module: &Module,
+    target_base: &Path,
+    config: &Config,
+) -> Result<Option<SyncStats>> {
+    let dst = target_base.join(&module.id);
+
+    let has_content = defs::BUILTIN_PARTITIONS.iter().any(|p| {
+        let part_path = module.source_path.join(p);
+
+        part_path.exists() && has_files_recursive(&part_path)
+    });
+
+    let has_overlay_content = defs::BUILTIN_PARTITIONS
+        .iter()
+        .any(|p| module.rules.is_overlay_capable(p) && module.source_path.join(p).exists());
+
+    if !has_content {
+        log::debug!("Skipping module: {}", module.id);
+
+        return Ok(None);
+    }
+
+    if !has_overlay_content {
+        log::debug!("Skipping sync for Magic Mount module: {}", module.id);
+
+        return Ok(None);
+    }
+
+    let manifest = manifest_of(module, config);
+
+    let forced = config.force_resync.iter().any(|id| id == &module.id);
+
+    let previous = if forced || !dst.exists() {
+        None
+    } else {
+        SyncManifest::load(&dst)
+    };
 
-            if dst.exists()
-                && let Err(e) = fs::remove_dir_all(&dst)
+    if previous.as_ref() == Some(&manifest) {
+        log::debug!("Skipping module: {} (Unchanged)", module.id);
+
+        return Ok(None);
+    }
+
+    let result = match &previous {
+        Some(previous)
+            if previous.carving == manifest.carving
+                && previous.version_code == manifest.version_code =>
+        {
+            log::info!("Syncing module: {} (Delta)", module.id);
+
+            delta_sync(&module.source_path, &dst, previous, &manifest)
+        }
+        _ => {
+            if forced {
+                log::info!("Syncing module: {} (Forced)", module.id);
+            } else if let Some(previous) = &previous
+                && previous.version_code != manifest.version_code
             {
-                log::warn!("Failed to clean target dir for {}: {}", module.id, e);
+                log::info!(
+                    "Syncing module: {} (versionCode {:?} -> {:?})",
+                    module.id,
+                    previous.version_code,
+                    manifest.version_code
+                );
+            } else {
+                log::info!("Syncing module: {} (Updated/New)", module.id);
             }
 
-            if let Err(e) = utils::sync_dir(&module.source_path, &dst) {
-                log::error!("Failed to sync module {}: {}", module.id, e);
+            full_sync(&module.source_path, &dst, &module.id, &manifest)
+        }
+    };
+
+    match result {
+        Ok(stats) => {
+            log::info!(
+                "Synced {}: +{} files ({} B), ~{} files ({} B), -{} files ({} B)",
+                module.id,
+                stats.added_files,
+                stats.added_bytes,
+                stats.updated_files,
+                stats.updated_bytes,
+                stats.removed_files,
+                stats.removed_bytes
+            );
+
+            if let Err(e) = manifest.save(&dst) {
+                log::warn!("Failed to write sync manifest for {}: {}", module.id, e);
+            }
+
+            Ok(Some(stats))
+        }
+        Err(e) => {
+            let _ = fs::remove_file(dst.join(MANIFEST_FILE));
+
+            Err(e)
+        }
+    }
+}
+
+fn full_sync(
+    src: &Path,
+    dst: &Path,
+    module_id: &str,
+    manifest: &SyncManifest,
+) -> Result<SyncStats> {
+    if dst.exists()
+        && let Err(e) = fs::remove_dir_all(dst)
+    {
+        log::warn!("Failed to clean target dir for {}: {}", module_id, e);
+    }
+
+    utils::sync_dir(src, dst)?;
+
+    carve_module(dst, module_id, &manifest.carving);
+
+    repair_module_contexts(dst, module_id);
+
+    let mut stats = SyncStats {
+        full_copy: true,
+        ..Default::default()
+    };
+
+    for entry in manifest.entries.values() {
+        if !is_kind(entry.mode, libc::S_IFDIR) {
+            stats.added_files += 1;
+
+            stats.added_bytes += entry.size;
+        }
+    }
+
+    Ok(stats)
+}
+
+fn is_kind(mode: u32, kind: libc::mode_t) -> bool {
+    mode & libc::S_IFMT == kind
+}
+
+fn remove_any(path: &Path) -> std::io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Brings an existing synced copy in line with the source by touching only
+/// the entries that differ between the two manifests.
+fn delta_sync(
+    src: &Path,
+    dst: &Path,
+    previous: &SyncManifest,
+    current: &SyncManifest,
+) -> Result<SyncStats> {
+    let mut stats = SyncStats::default();
+
+    let mut touched = Vec::new();
+
+    for (rel, old) in previous.entries.iter().rev() {
+        if current.entries.contains_key(rel) {
+            continue;
+        }
+
+        remove_any(&dst.join(rel))?;
+
+        if !is_kind(old.mode, libc::S_IFDIR) {
+            stats.removed_files += 1;
+
+            stats.removed_bytes += old.size;
+        }
+    }
+
+    for (rel, entry) in &current.entries {
+        let src_path = src.join(rel);
+
+        let dst_path = dst.join(rel);
+
+        let is_dir = is_kind(entry.mode, libc::S_IFDIR);
+
+        let old = previous.entries.get(rel);
+
+        if old == Some(entry) {
+            continue;
+        }
+
+        let content_changed = match old {
+            None => true,
+            Some(old) if old.mode & libc::S_IFMT != entry.mode & libc::S_IFMT => {
+                remove_any(&dst_path)?;
+
+                true
+            }
+            Some(old) => {
+                !is_dir
+                    && (old.size != entry.size
+                        || old.mtime != entry.mtime
+                        || old.mtime_nsec != entry.mtime_nsec
+                        || old.hash != entry.hash)
+            }
+        };
+
+        if is_dir {
+            utils::copy_dir_entry(&src_path, &dst_path)?;
+        } else if content_changed {
+            if let Some(parent) = dst_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            let bytes = utils::copy_file_entry(&src_path, &dst_path)?;
+
+            if old.is_some() {
+                stats.updated_files += 1;
+
+                stats.updated_bytes += bytes;
             } else {
-                repair_module_contexts(&dst, &module.id);
+                stats.added_files += 1;
+
+                stats.added_bytes += bytes;
             }
         } else {
-            log::debug!("Skipping module: {}", module.id);
+            utils::copy_entry_metadata(&src_path, &dst_path)?;
         }
-    });
 
-    Ok(())
+        let partition = rel.split('/').next().unwrap_or_default();
+
+        if defs::BUILTIN_PARTITIONS.contains(&partition) {
+            touched.push(dst_path);
+        }
+    }
+
+    for (rel, entry) in current.entries.iter().rev() {
+        if is_kind(entry.mode, libc::S_IFDIR) {
+            utils::copy_entry_metadata(&src.join(rel), &dst.join(rel))?;
+        }
+    }
+
+    for path in touched {
+        let _ = repair_path_context(dst, &path);
+    }
+
+    Ok(stats)
 }
 
 fn prune_orphaned_modules(modules: &[Module], target_base: &Path) -> Result<()> {
@@ -85,22 +475,36 @@ fn prune_orphaned_modules(modules: &[Module], target_base: &Path) -> Result<()>
     Ok(())
 }
 
-fn should_sync(src: &Path, dst: &Path) -> bool {
-    if !dst.exists() {
-        return true;
+/// Paths (relative to the module root) removed from the storage copy because
+/// they are magic mounted from the module source or ignored.
+fn carving_of(rules: &ModuleRules) -> Vec<String> {
+    let mut carving = Vec::new();
+
+    for part in defs::BUILTIN_PARTITIONS {
+        if !rules.is_overlay_capable(part) {
+            carving.push(part.to_string());
+        } else {
+            carving.extend(rules.carved_paths(part).into_iter().map(String::from));
+        }
     }
 
-    let src_prop = src.join("module.prop");
+    carving
+}
 
-    let dst_prop = dst.join("module.prop");
+fn carve_module(module_root: &Path, module_id: &str, carving: &[String]) {
+    for relative in carving {
+        let path = module_root.join(relative);
 
-    if !src_prop.exists() || !dst_prop.exists() {
-        return true;
-    }
+        let result = match fs::symlink_metadata(&path) {
+            Ok(meta) if meta.is_dir() => fs::remove_dir_all(&path),
+            Ok(_) => fs::remove_file(&path),
+            Err(_) => continue,
+        };
 
-    match (fs::read(&src_prop), fs::read(&dst_prop)) {
-        (Ok(s), Ok(d)) => s != d,
-        _ => true,
+        match result {
+            Ok(_) => log::debug!("Carved {}/{} out of overlay storage", module_id, relative),
+            Err(e) => log::warn!("Failed to carve {}/{}: {}", module_id, relative, e),
+        }
     }
 }
 
@@ -116,11 +520,7 @@ fn repair_module_contexts(module_root: &Path, module_id: &str) {
     }
 }
 
-fn recursive_context_repair(base: &Path, current: &Path) -> Result<()> {
-    if !current.exists() {
-        return Ok(());
-    }
-
+fn repair_path_context(base: &Path, current: &Path) -> Result<()> {
     let file_name = current.file_name().and_then(|n| n.to_str()).unwrap_or("");
 
     if (file_name == "upperdir" || file_name == "workdir")
@@ -142,6 +542,16 @@ fn recursive_context_repair(base: &Path, current: &Path) -> Result<()> {
         }
     }
 
+    Ok(())
+}
+
+fn recursive_context_repair(base: &Path, current: &Path) -> Result<()> {
+    if !current.exists() {
+        return Ok(());
+    }
+
+    repair_path_context(base, current)?;
+
     if current.is_dir()
         && let Ok(entries) = fs::read_dir(current)
     {
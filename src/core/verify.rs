@@ -0,0 +1,339 @@
+// Copyright 2025 Meta-Hybrid Mount Authors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use procfs::process::{MountInfo, Process};
+use serde::Serialize;
+
+use crate::{
+    conf::config::Config,
+    core::state::RuntimeState,
+    defs,
+    mount::journal::{MountRecord, MountRole},
+};
+
+/// Per-module or per-partition tally of recorded mounts by live status.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MountCheck {
+    pub ok: usize,
+    pub missing: usize,
+    pub shadowed: usize,
+    pub extra: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MountFinding {
+    pub target: PathBuf,
+    pub fs_type: String,
+    pub partition: String,
+    pub modules: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct VerifyReport {
+    pub healthy: bool,
+    pub checked: usize,
+    pub missing: Vec<MountFinding>,
+    pub shadowed: Vec<MountFinding>,
+    pub extra: Vec<MountFinding>,
+    pub partitions: BTreeMap<String, MountCheck>,
+    pub modules: BTreeMap<String, MountCheck>,
+}
+
+enum Status {
+    Ok,
+    Missing,
+    Shadowed(String),
+}
+
+fn read_mountinfo() -> Result<Vec<MountInfo>> {
+    let infos = match Process::new(1).and_then(|p| p.mountinfo()) {
+        Ok(infos) => infos,
+        Err(e) => {
+            log::warn!("Cannot read /proc/1/mountinfo ({}), using own namespace", e);
+
+            Process::myself()
+                .and_then(|p| p.mountinfo())
+                .context("Failed to read mountinfo")?
+        }
+    };
+
+    Ok(infos.into_iter().collect())
+}
+
+fn partition_of(target: &Path, mount_point: &Path) -> String {
+    if target.starts_with(mount_point) || target.starts_with(defs::RUN_DIR) {
+        return "storage".to_string();
+    }
+
+    target
+        .components()
+        .find_map(|c| match c {
+            Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .unwrap_or_else(|| "/".to_string())
+}
+
+/// Whether a live mount can be the one described by `record`. Binds and
+/// moved mounts carry the fs type and source of their origin, so only the
+/// place counts for them.
+fn matches(record: &MountRecord, info: &MountInfo, mount_source: &str) -> bool {
+    let source = match (record.role, record.fs_type.as_str()) {
+        (_, "bind" | "move") => return true,
+        (MountRole::Magic, _) => mount_source,
+        (MountRole::Storage, "tmpfs") | (MountRole::Overlay, _) => record.source.as_str(),
+        (MountRole::Staging | MountRole::ChildOverlaid, _) => record.source.as_str(),
+        _ => return info.fs_type == record.fs_type,
+    };
+
+    info.fs_type == record.fs_type && info.mount_source.as_deref() == Some(source)
+}
+
+/// What currently sits at the target of a recorded mount.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Holder {
+    /// Nothing is mounted there any more.
+    Nobody,
+    /// The recorded mount is the topmost one.
+    Record,
+    /// The recorded mount is still there, below a foreign one.
+    Covered,
+    /// Only foreign mounts are left, e.g. the stock partition.
+    Other,
+}
+
+/// Decides from `infos` whether the topmost mount at `record.target` is the
+/// one the record describes, so it can be detached without hitting a mount
+/// that was never ours.
+pub fn holder(record: &MountRecord, infos: &[MountInfo], mount_source: &str) -> Holder {
+    let here: Vec<&MountInfo> = infos
+        .iter()
+        .filter(|info| info.mount_point == record.target)
+        .collect();
+
+    let top = here
+        .iter()
+        .rev()
+        .find(|info| !here.iter().any(|other| other.pid == info.mnt_id));
+
+    match top {
+        None => Holder::Nobody,
+        Some(info) if matches(record, info, mount_source) => Holder::Record,
+        Some(_) if here.iter().any(|info| matches(record, info, mount_source)) => Holder::Covered,
+        Some(_) => Holder::Other,
+    }
+}
+
+fn check(
+    record: &MountRecord,
+    infos: &[MountInfo],
+    owned: &HashSet<PathBuf>,
+    mount_source: &str,
+) -> Status {
+    let here: Vec<(usize, &MountInfo)> = infos
+        .iter()
+        .enumerate()
+        .filter(|(_, info)| info.mount_point == record.target)
+        .collect();
+
+    let is_top = |info: &MountInfo| !here.iter().any(|(_, other)| other.pid == info.mnt_id);
+
+    let Some(&(index, found)) = here
+        .iter()
+        .rev()
+        .find(|(_, info)| matches(record, info, mount_source))
+    else {
+        return Status::Missing;
+    };
+
+    if !is_top(found) {
+        let top = here
+            .iter()
+            .map(|(_, info)| *info)
+            .find(|info| is_top(info))
+            .map(|info| info.fs_type.clone())
+            .unwrap_or_default();
+
+        return Status::Shadowed(format!("covered by {} at the same path", top));
+    }
+
+    let covering = infos[index + 1..].iter().find(|info| {
+        info.mount_point != record.target
+            && record.target.starts_with(&info.mount_point)
+            && !owned.contains(&info.mount_point)
+    });
+
+    match covering {
+        Some(info) => Status::Shadowed(format!(
+            "covered by {} on {}",
+            info.fs_type,
+            info.mount_point.display()
+        )),
+        None => Status::Ok,
+    }
+}
+
+/// Cross-checks the mounts recorded in the runtime state against the live
+/// mount table of init's namespace.
+pub fn verify(state: &RuntimeState, config: &Config) -> Result<VerifyReport> {
+    let mount_source = config.mountsource.as_str();
+
+    let roots = [state.mount_point.as_path(), config.moduledir.as_path()];
+
+    let infos = read_mountinfo()?;
+
+    let active: Vec<&MountRecord> = state.mounts.iter().filter(|r| r.is_active()).collect();
+
+    let owned: HashSet<PathBuf> = active.iter().map(|r| r.target.clone()).collect();
+
+    let mut report = VerifyReport {
+        checked: active.len(),
+        ..Default::default()
+    };
+
+    for record in &active {
+        let partition = if record.partition.is_empty() {
+            partition_of(&record.target, &state.mount_point)
+        } else {
+            record.partition.clone()
+        };
+
+        let modules = record.module_ids(&roots);
+
+        let status = check(record, &infos, &owned, mount_source);
+
+        let tally = |check: &mut MountCheck| match status {
+            Status::Ok => check.ok += 1,
+            Status::Missing => check.missing += 1,
+            Status::Shadowed(_) => check.shadowed += 1,
+        };
+
+        tally(report.partitions.entry(partition.clone()).or_default());
+
+        for id in &modules {
+            tally(report.modules.entry(id.clone()).or_default());
+        }
+
+        let finding = |detail: Option<String>| MountFinding {
+            target: record.target.clone(),
+            fs_type: record.fs_type.clone(),
+            partition: partition.clone(),
+            modules: modules.clone(),
+            detail,
+        };
+
+        match status {
+            Status::Ok => {}
+            Status::Missing => report.missing.push(finding(None)),
+            Status::Shadowed(detail) => report.shadowed.push(finding(Some(detail))),
+        }
+    }
+
+    // Magic mount skeletons hold unrecorded binds of module files.
+    let skeletons: Vec<&Path> = active
+        .iter()
+        .filter(|r| r.role == MountRole::Magic && r.fs_type == "tmpfs")
+        .map(|r| r.target.as_path())
+        .collect();
+
+    let sources = [defs::KSU_OVERLAY_SOURCE, mount_source];
+
+    let mut seen = HashSet::new();
+
+    for info in &infos {
+        let ours = info
+            .mount_source
+            .as_deref()
+            .is_some_and(|source| sources.contains(&source));
+
+        if !ours
+            || !matches!(info.fs_type.as_str(), "overlay" | "tmpfs")
+            || owned.contains(&info.mount_point)
+            || skeletons.iter().any(|s| info.mount_point.starts_with(s))
+            || !seen.insert(info.mount_point.clone())
+        {
+            continue;
+        }
+
+        let partition = partition_of(&info.mount_point, &state.mount_point);
+
+        report
+            .partitions
+            .entry(partition.clone())
+            .or_default()
+            .extra += 1;
+
+        report.extra.push(MountFinding {
+            target: info.mount_point.clone(),
+            fs_type: info.fs_type.clone(),
+            partition,
+            modules: Vec::new(),
+            detail: info.mount_source.clone(),
+        });
+    }
+
+    report.healthy = report.missing.is_empty() && report.shadowed.is_empty();
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STOCK: &str = "36 25 253:0 / /system ro,relatime shared:1 - ext4 /dev/block/dm-0 ro";
+
+    const OURS: &str = "90 36 0:50 / /system ro,relatime shared:2 - overlay KSU ro,lowerdir=/a";
+
+    const FOREIGN: &str = "91 90 0:51 / /system rw,relatime shared:3 - tmpfs tmpfs rw";
+
+    fn infos(lines: &[&str]) -> Vec<MountInfo> {
+        lines
+            .iter()
+            .map(|line| MountInfo::from_line(line).unwrap())
+            .collect()
+    }
+
+    fn overlay() -> MountRecord {
+        MountRecord::new(Path::new("/system"), "overlay", "KSU", MountRole::Overlay)
+    }
+
+    #[test]
+    fn own_mount_on_top() {
+        assert_eq!(
+            holder(&overlay(), &infos(&[STOCK, OURS]), "KSU"),
+            Holder::Record
+        );
+    }
+
+    #[test]
+    fn stale_record_leaves_stock_mount_alone() {
+        assert_eq!(holder(&overlay(), &infos(&[STOCK]), "KSU"), Holder::Other);
+    }
+
+    #[test]
+    fn covered_mount_is_not_detached() {
+        assert_eq!(
+            holder(&overlay(), &infos(&[STOCK, OURS, FOREIGN]), "KSU"),
+            Holder::Covered
+        );
+    }
+
+    #[test]
+    fn unmounted_target() {
+        let record = MountRecord::new(Path::new("/vendor"), "overlay", "KSU", MountRole::Overlay);
+
+        assert_eq!(
+            holder(&record, &infos(&[STOCK, OURS]), "KSU"),
+            Holder::Nobody
+        );
+    }
+}
@@ -1,51 +1,479 @@
 // Copyright 2025 Meta-Hybrid Mount Authors
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use std::path::{Path, PathBuf};
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::{Path, PathBuf},
+};
 
+use regex_lite::Regex;
 use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
 
 use crate::{conf::config::WinnowingTable, core::planner::ConflictEntry};
 
 #[derive(Debug, Serialize, Deserialize)]
-
 pub struct ChaffConflict {
     pub path: PathBuf,
     pub contenders: Vec<String>,
     pub selected: String,
     pub is_forced: bool,
+    #[serde(default)]
+    pub preferred: Option<String>,
+    #[serde(default)]
+    pub matched_rule: Option<String>,
+    #[serde(default)]
+    pub rule_kind: Option<RuleKind>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleKind {
+    Exact,
+    Prefix,
+    Glob,
+}
+
+#[derive(Debug, Clone)]
+pub struct RuleMatch {
+    pub pattern: String,
+    pub module_id: String,
+    pub kind: RuleKind,
+}
+
+/// Compiled form of a `WinnowingTable`.
+///
+/// Keys of the table are classified as exact paths, directory prefixes
+/// (`/system/fonts/` or `/system/fonts/**`) or globs (`*`, `**`, `?`).
+/// Lookups try exact paths first, then the longest matching prefix, then
+/// the most specific glob.
+pub struct Sieve {
+    exact: HashMap<String, String>,
+    prefixes: Vec<(String, String, String)>,
+    globs: Vec<(Regex, String, String)>,
+}
+
+impl Sieve {
+    pub fn new(table: &WinnowingTable) -> Self {
+        let mut exact = HashMap::new();
+
+        let mut prefixes = Vec::new();
+
+        let mut globs = Vec::new();
+
+        for (pattern, module_id) in &table.rules {
+            let dir = pattern
+                .strip_suffix("/**")
+                .or_else(|| pattern.strip_suffix('/'));
+
+            match dir {
+                Some(dir) if !has_wildcard(dir) => {
+                    prefixes.push((format!("{}/", dir), pattern.clone(), module_id.clone()));
+                }
+                _ if has_wildcard(pattern) => match glob_to_regex(pattern) {
+                    Ok(re) => globs.push((re, pattern.clone(), module_id.clone())),
+                    Err(e) => log::warn!("Invalid winnowing pattern '{}': {}", pattern, e),
+                },
+                _ => {
+                    exact.insert(pattern.clone(), module_id.clone());
+                }
+            }
+        }
+
+        prefixes.sort_by(|a, b| b.0.len().cmp(&a.0.len()).then_with(|| a.1.cmp(&b.1)));
+
+        globs.sort_by(|a, b| {
+            literal_len(&b.1)
+                .cmp(&literal_len(&a.1))
+                .then_with(|| a.1.cmp(&b.1))
+        });
+
+        Self {
+            exact,
+            prefixes,
+            globs,
+        }
+    }
+
+    /// Whether any rule can match a path below `/<partition>/`. Globs count
+    /// when their literal head is compatible with it.
+    pub fn covers(&self, partition: &str) -> bool {
+        let root = format!("/{}/", partition);
+
+        self.exact.keys().any(|path| path.starts_with(&root))
+            || self
+                .prefixes
+                .iter()
+                .any(|(dir, _, _)| dir.starts_with(&root))
+            || self.globs.iter().any(|(_, pattern, _)| {
+                let head = &pattern[..pattern.find(['*', '?']).unwrap_or(pattern.len())];
+
+                head.starts_with(&root) || root.starts_with(head)
+            })
+    }
+
+    pub fn matching(&self, path: &str) -> Option<RuleMatch> {
+        if let Some(module_id) = self.exact.get(path) {
+            return Some(RuleMatch {
+                pattern: path.to_string(),
+                module_id: module_id.clone(),
+                kind: RuleKind::Exact,
+            });
+        }
+
+        if let Some((_, pattern, module_id)) = self
+            .prefixes
+            .iter()
+            .find(|(dir, _, _)| path.starts_with(dir.as_str()))
+        {
+            return Some(RuleMatch {
+                pattern: pattern.clone(),
+                module_id: module_id.clone(),
+                kind: RuleKind::Prefix,
+            });
+        }
+
+        self.globs
+            .iter()
+            .find(|(re, _, _)| re.is_match(path))
+            .map(|(_, pattern, module_id)| RuleMatch {
+                pattern: pattern.clone(),
+                module_id: module_id.clone(),
+                kind: RuleKind::Glob,
+            })
+    }
+}
+
+fn has_wildcard(pattern: &str) -> bool {
+    pattern.contains(['*', '?'])
+}
+
+fn literal_len(pattern: &str) -> usize {
+    pattern.chars().filter(|c| !matches!(c, '*' | '?')).count()
+}
+
+fn glob_to_regex(pattern: &str) -> Result<Regex, regex_lite::Error> {
+    let mut re = String::from("^");
+
+    let mut chars = pattern.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+
+                    re.push_str("(?:.*/)?");
+                } else {
+                    re.push_str(".*");
+                }
+            }
+            '*' => re.push_str("[^/]*"),
+            '?' => re.push_str("[^/]"),
+            _ => re.push_str(&regex_lite::escape(&c.to_string())),
+        }
+    }
+
+    re.push('$');
+
+    Regex::new(&re)
+}
+
+fn layer_module_id(layer: &Path) -> String {
+    layer
+        .parent()
+        .and_then(|p| p.file_name())
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "UNKNOWN".into())
+}
+
+/// Reorders the lowerdirs of one partition so that every module forced by a
+/// winnowing rule sits above the other layers shipping the same path.
+/// Layers not involved in any rule keep their relative order.
+pub fn thresh_layers(partition: &str, layers: &mut Vec<PathBuf>, table: &WinnowingTable) {
+    if layers.len() < 2 {
+        return;
+    }
+
+    let sieve = Sieve::new(table);
+
+    if !sieve.covers(partition) {
+        return;
+    }
+
+    let ids: Vec<String> = layers.iter().map(|l| layer_module_id(l)).collect();
+
+    let mut holders: HashMap<String, Vec<usize>> = HashMap::new();
+
+    for (i, layer) in layers.iter().enumerate() {
+        for entry in WalkDir::new(layer).min_depth(1).into_iter().flatten() {
+            if entry.file_type().is_dir() {
+                continue;
+            }
+
+            if let Ok(rel) = entry.path().strip_prefix(layer) {
+                holders
+                    .entry(rel.to_string_lossy().to_string())
+                    .or_default()
+                    .push(i);
+            }
+        }
+    }
+
+    let mut winners_over: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); layers.len()];
+
+    for (relative, holding) in holders {
+        if holding.len() < 2 {
+            continue;
+        }
+
+        let Some(rule) = sieve.matching(&format!("/{}/{}", partition, relative)) else {
+            continue;
+        };
+
+        let Some(&winner) = holding.iter().find(|&&i| ids[i] == rule.module_id) else {
+            continue;
+        };
+
+        for &i in &holding {
+            if i != winner {
+                winners_over[i].insert(winner);
+            }
+        }
+    }
+
+    if winners_over.iter().all(|w| w.is_empty()) {
+        return;
+    }
+
+    fn place(
+        i: usize,
+        winners_over: &[BTreeSet<usize>],
+        ids: &[String],
+        visiting: &mut [bool],
+        placed: &mut [bool],
+        order: &mut Vec<usize>,
+    ) {
+        if placed[i] {
+            return;
+        }
+
+        if visiting[i] {
+            log::warn!(
+                "Winnowing rules form a cycle around module '{}', ignoring the conflicting rule",
+                ids[i]
+            );
+
+            return;
+        }
+
+        visiting[i] = true;
+
+        for &w in &winners_over[i] {
+            place(w, winners_over, ids, visiting, placed, order);
+        }
+
+        visiting[i] = false;
+
+        if !placed[i] {
+            placed[i] = true;
+
+            order.push(i);
+        }
+    }
+
+    let mut visiting = vec![false; layers.len()];
+
+    let mut placed = vec![false; layers.len()];
+
+    let mut order = Vec::with_capacity(layers.len());
+
+    for i in 0..layers.len() {
+        place(
+            i,
+            &winners_over,
+            &ids,
+            &mut visiting,
+            &mut placed,
+            &mut order,
+        );
+    }
+
+    if order.iter().enumerate().any(|(pos, &i)| pos != i) {
+        log::info!(
+            "Winnowing: reordered layers for /{}: {:?}",
+            partition,
+            order.iter().map(|&i| ids[i].as_str()).collect::<Vec<_>>()
+        );
+    }
+
+    *layers = order.into_iter().map(|i| layers[i].clone()).collect();
 }
 
+/// Contenders are listed in lowerdir order, so the first one is the copy
+/// that is actually visible once the overlay is mounted.
 pub fn sift_conflicts(conflicts: Vec<ConflictEntry>, table: &WinnowingTable) -> Vec<ChaffConflict> {
+    let sieve = Sieve::new(table);
+
     conflicts
         .into_iter()
         .map(|c| {
             let path_str = format!("/{}/{}", c.partition, c.relative_path);
 
-            let forced_module = table.get_preferred_module(Path::new(&path_str));
+            let rule = sieve.matching(&path_str);
 
-            let selected = if let Some(forced) = &forced_module {
-                if c.contending_modules.contains(forced) {
-                    forced.clone()
-                } else {
-                    c.contending_modules
-                        .last()
-                        .unwrap_or(&"unknown".to_string())
-                        .clone()
-                }
-            } else {
-                c.contending_modules
-                    .last()
-                    .unwrap_or(&"unknown".to_string())
-                    .clone()
-            };
+            let preferred = rule.as_ref().map(|r| r.module_id.clone());
+
+            let selected = c
+                .contending_modules
+                .first()
+                .cloned()
+                .unwrap_or_else(|| "unknown".to_string());
 
             ChaffConflict {
                 path: PathBuf::from(path_str),
                 contenders: c.contending_modules,
+                is_forced: preferred.as_ref() == Some(&selected),
                 selected,
-                is_forced: forced_module.is_some(),
+                preferred,
+                matched_rule: rule.as_ref().map(|r| r.pattern.clone()),
+                rule_kind: rule.map(|r| r.kind),
             }
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sieve(rules: &[(&str, &str)]) -> Sieve {
+        let mut table = WinnowingTable::default();
+
+        for (pattern, module_id) in rules {
+            table.set_rule(pattern, module_id);
+        }
+
+        Sieve::new(&table)
+    }
+
+    fn winner(sieve: &Sieve, path: &str) -> Option<(String, RuleKind)> {
+        sieve.matching(path).map(|m| (m.module_id, m.kind))
+    }
+
+    #[test]
+    fn exact_beats_prefix_and_glob() {
+        let sieve = sieve(&[
+            ("/system/fonts/Roboto.ttf", "exact"),
+            ("/system/fonts/", "prefix"),
+            ("/system/fonts/*.ttf", "glob"),
+        ]);
+
+        assert_eq!(
+            winner(&sieve, "/system/fonts/Roboto.ttf"),
+            Some(("exact".into(), RuleKind::Exact))
+        );
+
+        assert_eq!(
+            winner(&sieve, "/system/fonts/Noto.ttf"),
+            Some(("prefix".into(), RuleKind::Prefix))
+        );
+    }
+
+    #[test]
+    fn longest_prefix_wins() {
+        let sieve = sieve(&[("/system/", "outer"), ("/system/fonts/**", "inner")]);
+
+        assert_eq!(
+            winner(&sieve, "/system/fonts/a/b.ttf"),
+            Some(("inner".into(), RuleKind::Prefix))
+        );
+
+        assert_eq!(
+            winner(&sieve, "/system/etc/hosts"),
+            Some(("outer".into(), RuleKind::Prefix))
+        );
+
+        assert_eq!(winner(&sieve, "/systemx/file"), None);
+    }
+
+    #[test]
+    fn most_specific_glob_wins() {
+        let sieve = sieve(&[
+            ("/system/**/*.ttf", "any"),
+            ("/system/fonts/*.ttf", "fonts"),
+        ]);
+
+        assert_eq!(
+            winner(&sieve, "/system/fonts/Roboto.ttf"),
+            Some(("fonts".into(), RuleKind::Glob))
+        );
+
+        assert_eq!(
+            winner(&sieve, "/system/fonts/sub/Roboto.ttf"),
+            Some(("any".into(), RuleKind::Glob))
+        );
+    }
+
+    #[test]
+    fn prefix_beats_glob() {
+        let sieve = sieve(&[("/system/fonts/*.ttf", "glob"), ("/system/**", "prefix")]);
+
+        assert_eq!(
+            winner(&sieve, "/system/fonts/Roboto.ttf"),
+            Some(("prefix".into(), RuleKind::Prefix))
+        );
+    }
+
+    #[test]
+    fn glob_translation() {
+        let single = glob_to_regex("/system/*.ttf").unwrap();
+
+        assert!(single.is_match("/system/a.ttf"));
+
+        assert!(!single.is_match("/system/fonts/a.ttf"));
+
+        let question = glob_to_regex("/etc/host?").unwrap();
+
+        assert!(question.is_match("/etc/hosts"));
+
+        assert!(!question.is_match("/etc/host/"));
+
+        let deep = glob_to_regex("/system/**/lib*.so").unwrap();
+
+        assert!(deep.is_match("/system/libfoo.so"));
+
+        assert!(deep.is_match("/system/lib64/hw/libfoo.so"));
+
+        assert!(!deep.is_match("/vendor/libfoo.so"));
+
+        let escaped = glob_to_regex("/system/a+b(1).*").unwrap();
+
+        assert!(escaped.is_match("/system/a+b(1).txt"));
+
+        assert!(!escaped.is_match("/system/aab1.txt"));
+    }
+
+    #[test]
+    fn covers_only_partitions_with_rules() {
+        let sieve = sieve(&[
+            ("/system/fonts/Roboto.ttf", "exact"),
+            ("/vendor/etc/", "prefix"),
+            ("/product/**/*.apk", "glob"),
+        ]);
+
+        for partition in ["system", "vendor", "product"] {
+            assert!(sieve.covers(partition), "{}", partition);
+        }
+
+        assert!(!sieve.covers("odm"));
+
+        assert!(!sieve.covers("sys"));
+
+        assert!(!Sieve::new(&WinnowingTable::default()).covers("system"));
+
+        assert!(self::sieve(&[("/*/etc/hosts", "any")]).covers("odm"));
+    }
+}
@@ -9,6 +9,10 @@ pub const RUN_DIR: &str = "/data/adb/meta-hybrid/run/";
 
 pub const STATE_FILE: &str = "/data/adb/meta-hybrid/run/daemon_state.json";
 
+pub const MOUNT_JOURNAL_FILE: &str = "/data/adb/meta-hybrid/run/mount_journal.jsonl";
+
+pub const RULES_DIR: &str = "/data/adb/meta-hybrid/rules";
+
 pub const DAEMON_LOG_FILE: &str = "/data/adb/meta-hybrid/daemon.log";
 
 pub const DISABLE_FILE_NAME: &str = "disable";
@@ -22,7 +26,6 @@ pub const OVERLAY_SOURCE: &str = "KSU";
 pub const KSU_OVERLAY_SOURCE: &str = OVERLAY_SOURCE;
 
 #[allow(dead_code)]
-
 pub const SYSTEM_RW_DIR: &str = "/data/adb/meta-hybrid/rw";
 
 pub const MODULE_PROP_FILE: &str = "/data/adb/modules/meta-hybrid/module.prop";
@@ -40,11 +43,9 @@ pub const BUILTIN_PARTITIONS: &[&str] = &[
 ];
 
 #[allow(dead_code)]
-
 pub const REPLACE_DIR_FILE_NAME: &str = ".replace";
 
 #[allow(dead_code)]
-
 pub const REPLACE_DIR_XATTR: &str = "trusted.overlay.opaque";
 
 pub const TMPFS_CANDIDATES: &[&str] = &["/debug_ramdisk", "/patch_hw", "/oem", "/root", "/sbin"];
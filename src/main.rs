@@ -22,7 +22,6 @@ use conf::{
 use core::{OryzaEngine, executor, granary, inventory, planner, winnow};
 
 #[global_allocator]
-
 static GLOBAL: MiMalloc = MiMalloc;
 
 fn load_config(cli: &Cli) -> Result<Config> {
@@ -68,9 +67,17 @@ fn main() -> Result<()> {
                 cli_handlers::handle_save_rules(module, payload)?
             }
             Commands::Storage => cli_handlers::handle_storage()?,
+            Commands::Status { mounts } => cli_handlers::handle_status(*mounts)?,
             Commands::Modules => cli_handlers::handle_modules(&cli)?,
             Commands::Conflicts => cli_handlers::handle_conflicts(&cli)?,
             Commands::Diagnostics => cli_handlers::handle_diagnostics(&cli)?,
+            Commands::Verify => cli_handlers::handle_verify(&cli)?,
+            Commands::Apply { module } => cli_handlers::handle_apply(&cli, module)?,
+            Commands::Sync { force, modules } => cli_handlers::handle_sync(&cli, modules, *force)?,
+            Commands::Teardown => cli_handlers::handle_teardown(&cli)?,
+            Commands::Watchdog => cli_handlers::handle_watchdog(&cli)?,
+            Commands::Granary { command } => cli_handlers::handle_granary(&cli, command)?,
+            Commands::Profile { command } => cli_handlers::handle_profile(&cli, command)?,
             Commands::SystemAction { action, value } => {
                 cli_handlers::handle_system_action(&cli, action, value.as_deref())?
             }
@@ -89,8 +96,15 @@ fn main() -> Result<()> {
         cli.dry_run,
     );
 
+    // Snapshots must hold the base partition list, not the profile's.
+    let backup_config = config.clone();
+
+    if cli.partitions.is_empty() {
+        config.apply_profile();
+    }
+
     if !config.dry_run
-        && let Err(e) = granary::engage_ratoon_protocol()
+        && let Err(e) = granary::engage_ratoon_protocol(&config)
     {
         log::error!("Failed to engage Ratoon Protocol: {}", e);
     }
@@ -122,12 +136,12 @@ fn main() -> Result<()> {
 
         log::info!(":: DRY-RUN / DIAGNOSTIC MODE ::");
 
-        let module_list =
-            inventory::scan(&config.moduledir, &config).context("Inventory scan failed")?;
+        let scan =
+            inventory::scan_report(&config.moduledir, &config).context("Inventory scan failed")?;
 
-        log::info!(">> Inventory: Found {} modules", module_list.len());
+        log::info!(">> Inventory: Found {} modules", scan.modules.len());
 
-        let plan = planner::generate(&config, &module_list, &config.moduledir)
+        let plan = planner::generate(&config, &scan.modules, &config.moduledir)
             .context("Plan generation failed")?;
 
         plan.print_visuals();
@@ -159,7 +173,7 @@ fn main() -> Result<()> {
 
         log::info!(">> Running System Diagnostics...");
 
-        let issues = executor::diagnose_plan(&plan);
+        let issues = executor::diagnose_plan(&plan, &scan.issues);
 
         let mut critical_count = 0;
 
@@ -223,7 +237,7 @@ fn main() -> Result<()> {
 
     let img_path = Path::new(defs::BASE_DIR).join("modules.img");
 
-    if let Err(e) = granary::create_silo(&config, "Boot Backup", "Automatic Pre-Mount") {
+    if let Err(e) = granary::create_silo(&backup_config, "Boot Backup", "Automatic Pre-Mount") {
         log::warn!("Granary: Failed to create boot snapshot: {}", e);
     }
 
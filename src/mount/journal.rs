@@ -0,0 +1,329 @@
+// Copyright 2025 Meta-Hybrid Mount Authors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Component, Path, PathBuf},
+    sync::{Mutex, OnceLock},
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::defs;
+
+static JOURNAL: OnceLock<Mutex<Vec<MountRecord>>> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MountRole {
+    /// Backing storage holding the synced module content.
+    Storage,
+    /// Overlay over a partition root.
+    #[default]
+    Overlay,
+    /// Intermediate overlay stacked to stay below the lowerdir length limit.
+    Staging,
+    /// Stashed child mount moved back unchanged on top of an overlay.
+    ChildRestored,
+    /// Stashed child mount overlaid with module content.
+    ChildOverlaid,
+    /// Bind or tmpfs mount created by magic mount.
+    Magic,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MountResult {
+    #[default]
+    Mounted,
+    Failed,
+    /// Mounted, then detached again by the daemon before the boot finished.
+    Reverted,
+}
+
+/// A mount attempted by the daemon during the boot sequence.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MountRecord {
+    pub order: u64,
+    pub target: PathBuf,
+    pub fs_type: String,
+    pub source: String,
+    #[serde(default)]
+    pub role: MountRole,
+    /// Partition the mount serves. Staging overlays live below the run dir
+    /// but belong to the partition they are stacked into.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub partition: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lowerdirs: Vec<PathBuf>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub options: String,
+    #[serde(default)]
+    pub result: MountResult,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Why this mount replaced or was replaced by another strategy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fallback: Option<String>,
+    /// Pre-existing mounts below `target` that were stashed before mounting
+    /// over it and must be visible again once it is detached.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stashed_children: Vec<PathBuf>,
+    /// Module roots with content inside this mount. Magic skeletons hold the
+    /// unrecorded binds of several modules, not only the one in `source`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub contributors: Vec<PathBuf>,
+}
+
+impl MountRecord {
+    pub fn new(target: &Path, fs_type: &str, source: &str, role: MountRole) -> Self {
+        Self {
+            order: 0,
+            target: target.to_path_buf(),
+            fs_type: fs_type.to_string(),
+            source: source.to_string(),
+            role,
+            partition: partition_of(target),
+            lowerdirs: Vec::new(),
+            options: String::new(),
+            result: MountResult::Mounted,
+            error: None,
+            fallback: None,
+            stashed_children: Vec::new(),
+            contributors: Vec::new(),
+        }
+    }
+
+    pub fn partition(mut self, partition: impl Into<String>) -> Self {
+        self.partition = partition.into();
+
+        self
+    }
+
+    pub fn lowerdirs<P: AsRef<Path>>(mut self, lowerdirs: &[P]) -> Self {
+        self.lowerdirs = lowerdirs.iter().map(|p| p.as_ref().to_path_buf()).collect();
+
+        self
+    }
+
+    pub fn options(mut self, options: impl Into<String>) -> Self {
+        self.options = options.into();
+
+        self
+    }
+
+    pub fn stashed_children(mut self, children: Vec<PathBuf>) -> Self {
+        self.stashed_children = children;
+
+        self
+    }
+
+    pub fn contributors(mut self, roots: Vec<PathBuf>) -> Self {
+        self.contributors = roots;
+
+        self
+    }
+
+    pub fn fallback(mut self, reason: impl Into<String>) -> Self {
+        self.fallback = Some(reason.into());
+
+        self
+    }
+
+    pub fn failed(mut self, error: impl std::fmt::Display) -> Self {
+        self.result = MountResult::Failed;
+
+        self.error = Some(format!("{:#}", error));
+
+        self
+    }
+
+    /// Modules whose content this mount serves, judged by layer, source and
+    /// contributor paths below one of `roots` (storage and module directory).
+    pub fn module_ids(&self, roots: &[&Path]) -> Vec<String> {
+        let source = PathBuf::from(&self.source);
+
+        let mut ids: Vec<String> = self
+            .lowerdirs
+            .iter()
+            .chain(std::iter::once(&source))
+            .chain(&self.contributors)
+            .filter_map(|path| {
+                roots
+                    .iter()
+                    .find_map(|base| path.strip_prefix(base).ok())
+                    .and_then(|rel| rel.components().next())
+                    .map(|c| c.as_os_str().to_string_lossy().to_string())
+            })
+            .collect();
+
+        ids.sort();
+
+        ids.dedup();
+
+        ids
+    }
+
+    /// Whether the mount is still expected to be attached.
+    pub fn is_active(&self) -> bool {
+        self.result == MountResult::Mounted
+    }
+}
+
+/// Top-level directory of `target`, e.g. `system` for `/system/app`.
+pub fn partition_of(target: &Path) -> String {
+    target
+        .components()
+        .find_map(|c| match c {
+            Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .unwrap_or_default()
+}
+
+fn journal() -> &'static Mutex<Vec<MountRecord>> {
+    JOURNAL.get_or_init(|| Mutex::new(Vec::new()))
+}
+
+fn to_lines<'a>(records: impl IntoIterator<Item = &'a MountRecord>) -> Result<String> {
+    let mut out = String::new();
+
+    for record in records {
+        out.push_str(&serde_json::to_string(record)?);
+
+        out.push('\n');
+    }
+
+    Ok(out)
+}
+
+/// Appends records to the journal file. A later line with the same order
+/// supersedes an earlier one, so updates never rewrite the whole file.
+fn append<'a>(records: impl IntoIterator<Item = &'a MountRecord>) {
+    let result = to_lines(records).and_then(|lines| {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(defs::MOUNT_JOURNAL_FILE)?
+            .write_all(lines.as_bytes())?;
+
+        Ok(())
+    });
+
+    if let Err(e) = result {
+        log::warn!("Failed to persist mount journal: {:#}", e);
+    }
+}
+
+/// Starts a fresh journal, discarding anything recorded by a previous run.
+pub fn reset() {
+    let mut records = journal().lock().unwrap();
+
+    records.clear();
+
+    let _ = fs::remove_file(defs::MOUNT_JOURNAL_FILE);
+}
+
+/// Continues a journal saved by an earlier run, so later mounts extend it.
+pub fn resume(saved: Vec<MountRecord>) {
+    let mut records = journal().lock().unwrap();
+
+    *records = saved;
+
+    if let Err(e) = save(&records) {
+        log::warn!("Failed to persist mount journal: {:#}", e);
+    }
+}
+
+/// Order the next recorded mount will get.
+pub fn next_order() -> u64 {
+    journal().lock().unwrap().last().map_or(0, |r| r.order + 1)
+}
+
+/// Active mounts recorded at or after `order`, oldest first.
+pub fn active_since(order: u64) -> Vec<MountRecord> {
+    journal()
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|r| r.order >= order && r.is_active())
+        .cloned()
+        .collect()
+}
+
+pub fn record(mut record: MountRecord) {
+    let mut records = journal().lock().unwrap();
+
+    record.order = records.last().map_or(0, |r| r.order + 1);
+
+    append([&record]);
+
+    records.push(record);
+}
+
+/// Marks active mounts at or below `target` as reverted after they were
+/// detached again by the daemon itself.
+pub fn revert(target: &Path) {
+    let mut records = journal().lock().unwrap();
+
+    let mut changed = Vec::new();
+
+    for record in records
+        .iter_mut()
+        .filter(|r| r.is_active() && r.target.starts_with(target))
+    {
+        record.result = MountResult::Reverted;
+
+        changed.push(record.clone());
+    }
+
+    if !changed.is_empty() {
+        append(&changed);
+    }
+}
+
+pub fn entries() -> Vec<MountRecord> {
+    journal().lock().unwrap().clone()
+}
+
+pub fn load() -> Result<Vec<MountRecord>> {
+    let path = Path::new(defs::MOUNT_JOURNAL_FILE);
+
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let content = fs::read_to_string(path)?;
+
+    let mut records = BTreeMap::new();
+
+    for line in content.lines().filter(|l| !l.trim().is_empty()) {
+        // A line cut short by a crash is skipped rather than failing the load.
+        match serde_json::from_str::<MountRecord>(line) {
+            Ok(record) => {
+                records.insert(record.order, record);
+            }
+            Err(e) => log::warn!("Skipping damaged mount journal line: {}", e),
+        }
+    }
+
+    Ok(records.into_values().collect())
+}
+
+/// Rewrites the journal file with exactly `records`.
+pub fn save(records: &[MountRecord]) -> Result<()> {
+    fs::write(defs::MOUNT_JOURNAL_FILE, to_lines(records)?)?;
+
+    Ok(())
+}
+
+pub fn clear() -> Result<()> {
+    if Path::new(defs::MOUNT_JOURNAL_FILE).exists() {
+        fs::remove_file(defs::MOUNT_JOURNAL_FILE)?;
+    }
+
+    Ok(())
+}
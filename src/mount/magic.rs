@@ -3,7 +3,7 @@
 
 use std::{
     collections::hash_map::Entry,
-    collections::{HashMap, HashSet},
+    collections::{BTreeSet, HashMap, HashSet},
     fs::{self, DirEntry, create_dir, create_dir_all, read_dir, read_link},
     os::unix::fs::{MetadataExt, symlink},
     path::{Path, PathBuf},
@@ -20,8 +20,12 @@ use rustix::{
 };
 
 use crate::{
+    core::inventory::{ModuleRules, MountMode, UmountPolicy},
     defs::{DISABLE_FILE_NAME, REMOVE_FILE_NAME, SKIP_MOUNT_FILE_NAME},
-    mount::node::{Node, NodeFileType},
+    mount::{
+        journal::{self, MountRecord, MountRole},
+        node::{Node, NodeFileType},
+    },
     utils::{ensure_dir_exists, lgetfilecon, lsetfilecon},
 };
 
@@ -30,6 +34,45 @@ use crate::try_umount::send_unmountable;
 
 const ROOT_PARTITIONS: [&str; 4] = ["vendor", "system_ext", "product", "odm"];
 
+/// Selects which parts of a module root are collected by magic mount.
+#[derive(Debug, Clone, Default)]
+pub struct MagicScope {
+    pub rules: ModuleRules,
+    /// Partitions whose overlay-mode content is served by magic mount, either
+    /// because the planner demoted them or because OverlayFS failed.
+    pub partitions: HashSet<String>,
+}
+
+impl MagicScope {
+    pub fn new(rules: ModuleRules) -> Self {
+        Self {
+            rules,
+            partitions: HashSet::new(),
+        }
+    }
+
+    fn includes(&self, relative: &str) -> bool {
+        match self.rules.get_mode(relative) {
+            MountMode::Magic => true,
+            MountMode::Ignore => false,
+            MountMode::Overlay => {
+                let partition = relative.split('/').next().unwrap_or(relative);
+
+                self.partitions.contains(partition) || !self.rules.is_overlay_capable(relative)
+            }
+        }
+    }
+
+    pub fn touches(&self, partition: &str) -> bool {
+        self.includes(partition)
+            || self
+                .rules
+                .nested_paths(partition)
+                .into_iter()
+                .any(|k| self.includes(k))
+    }
+}
+
 fn merge_nodes(high: &mut Node, low: Node) {
     if high.module_path.is_none() {
         high.module_path = low.module_path;
@@ -54,7 +97,7 @@ fn merge_nodes(high: &mut Node, low: Node) {
 fn process_module(
     path: &Path,
     extra_partitions: &[String],
-    exclusion_list: Option<&HashSet<String>>,
+    scope: Option<&MagicScope>,
 ) -> Result<(Node, Node)> {
     let mut root = Node::new_root("");
 
@@ -67,11 +110,13 @@ fn process_module(
         return Ok((root, system));
     }
 
-    let is_excluded = |part: &str| -> bool {
-        if let Some(list) = exclusion_list {
-            list.contains(part)
-        } else {
-            false
+    let is_excluded = |part: &str| -> bool { scope.is_some_and(|s| !s.touches(part)) };
+
+    let keep = |part: &str| {
+        let part = part.to_string();
+
+        move |relative: &Path| {
+            scope.is_none_or(|s| s.includes(&format!("{}/{}", part, relative.to_string_lossy())))
         }
     };
 
@@ -79,7 +124,7 @@ fn process_module(
         let mod_system = path.join("system");
 
         if mod_system.is_dir() {
-            system.collect_module_files(&mod_system)?;
+            system.collect_module_files(&mod_system, keep("system"))?;
         }
     }
 
@@ -102,7 +147,7 @@ fn process_module(
                 node.module_path = None;
             }
 
-            node.collect_module_files(&mod_part)?;
+            node.collect_module_files(&mod_part, keep(partition))?;
         }
     }
 
@@ -130,7 +175,7 @@ fn process_module(
                     .entry(name)
                     .or_insert_with(|| Node::new_root(partition));
 
-                node.collect_module_files(&mod_part)?;
+                node.collect_module_files(&mod_part, keep(partition))?;
             }
         } else if path_of_root.is_dir() {
             let name = partition.clone();
@@ -143,7 +188,7 @@ fn process_module(
                     .entry(name)
                     .or_insert_with(|| Node::new_root(partition));
 
-                node.collect_module_files(&mod_part)?;
+                node.collect_module_files(&mod_part, keep(partition))?;
             }
         }
     }
@@ -151,18 +196,16 @@ fn process_module(
     Ok((root, system))
 }
 
+/// `module_paths` must be in precedence order: when two modules provide the
+/// same node, the one listed first is kept as the high node in `merge_nodes`.
 fn collect_module_files(
     module_paths: &[PathBuf],
     extra_partitions: &[String],
-    exclusions: &HashMap<PathBuf, HashSet<String>>,
+    scopes: &HashMap<PathBuf, MagicScope>,
 ) -> Result<Option<Node>> {
     let (mut final_root, mut final_system) = module_paths
         .par_iter()
-        .map(|path| {
-            let exclusion = exclusions.get(path);
-
-            process_module(path, extra_partitions, exclusion)
-        })
+        .map(|path| process_module(path, extra_partitions, scopes.get(path)))
         .reduce(
             || Ok((Node::new_root(""), Node::new_root("system"))),
             |a, b| {
@@ -264,22 +307,59 @@ where
     Ok(())
 }
 
-struct MagicMount {
+/// Decides per mount whether it is registered for try-umount, from the
+/// umount policies of the modules that contribute to it.
+#[derive(Clone, Copy)]
+struct UmountRules<'a> {
+    default: bool,
+    scopes: &'a HashMap<PathBuf, MagicScope>,
+}
+
+#[cfg_attr(not(any(target_os = "linux", target_os = "android")), allow(dead_code))]
+impl UmountRules<'_> {
+    fn policy_of(&self, module_path: &Path) -> UmountPolicy {
+        self.scopes
+            .iter()
+            .find_map(|(root, scope)| {
+                let relative = module_path.strip_prefix(root).ok()?;
+
+                Some(scope.rules.umount_policy(&relative.to_string_lossy()))
+            })
+            .unwrap_or_default()
+    }
+
+    fn subtree_policy(&self, node: &Node) -> UmountPolicy {
+        node.children.values().fold(
+            node.module_path
+                .as_deref()
+                .map(|p| self.policy_of(p))
+                .unwrap_or_default(),
+            |acc, child| acc.combine(self.subtree_policy(child)),
+        )
+    }
+
+    fn wants(&self, node: &Node) -> bool {
+        self.subtree_policy(node).should_register(self.default)
+    }
+}
+
+struct MagicMount<'a> {
     node: Node,
     path: PathBuf,
     work_dir_path: PathBuf,
     has_tmpfs: bool,
-    #[cfg(any(target_os = "linux", target_os = "android"))]
-    umount: bool,
+    umount: UmountRules<'a>,
+    roots: &'a [PathBuf],
 }
 
-impl MagicMount {
+impl<'a> MagicMount<'a> {
     fn new<P>(
         node: &Node,
         path: P,
         work_dir_path: P,
         has_tmpfs: bool,
-        #[cfg(any(target_os = "linux", target_os = "android"))] umount: bool,
+        umount: UmountRules<'a>,
+        roots: &'a [PathBuf],
     ) -> Self
     where
         P: AsRef<Path>,
@@ -289,9 +369,32 @@ impl MagicMount {
             path: path.as_ref().join(node.name.clone()),
             work_dir_path: work_dir_path.as_ref().join(node.name.clone()),
             has_tmpfs,
-            #[cfg(any(target_os = "linux", target_os = "android"))]
             umount,
+            roots,
+        }
+    }
+
+    /// Module roots providing anything below this node.
+    fn contributors(&self) -> Vec<PathBuf> {
+        fn walk(node: &Node, roots: &[PathBuf], found: &mut BTreeSet<PathBuf>) {
+            if let Some(root) = node
+                .module_path
+                .as_ref()
+                .and_then(|p| roots.iter().find(|r| p.starts_with(r)))
+            {
+                found.insert(root.clone());
+            }
+
+            for child in node.children.values().filter(|c| !c.skip) {
+                walk(child, roots, found);
+            }
         }
+
+        let mut found = BTreeSet::new();
+
+        walk(&self.node, self.roots, &mut found);
+
+        found.into_iter().collect()
     }
 
     fn check_tmpfs(&mut self) {
@@ -362,12 +465,24 @@ impl MagicMount {
                 self.work_dir_path.display()
             );
 
-            mount_bind(module_path, target_path).with_context(|| {
-                #[cfg(any(target_os = "linux", target_os = "android"))]
-                if self.umount {
-                    let _ = send_unmountable(target_path);
-                }
+            let bind_result = mount_bind(module_path, target_path);
 
+            if !self.has_tmpfs {
+                let record = MountRecord::new(
+                    &self.path,
+                    "bind",
+                    &module_path.to_string_lossy(),
+                    MountRole::Magic,
+                )
+                .options("bind,ro");
+
+                journal::record(match &bind_result {
+                    Ok(_) => record,
+                    Err(e) => record.failed(e),
+                });
+            }
+
+            bind_result.with_context(|| {
                 format!(
                     "mount module file {} -> {}",
                     module_path.display(),
@@ -379,6 +494,13 @@ impl MagicMount {
                 log::warn!("make file {} ro: {e:#?}", target_path.display());
             }
 
+            // Binds inside a tmpfs skeleton go with the tmpfs, which is
+            // registered once it is moved into place.
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            if !self.has_tmpfs && self.umount.wants(&self.node) {
+                let _ = send_unmountable(&self.path);
+            }
+
             Ok(())
         } else {
             bail!("cannot mount root file {}!", self.path.display());
@@ -397,6 +519,12 @@ impl MagicMount {
 
         let has_tmpfs = self.has_tmpfs || create_tmpfs;
 
+        let contributors = if create_tmpfs {
+            self.contributors()
+        } else {
+            Vec::new()
+        };
+
         if has_tmpfs {
             log::debug!(
                 "creating tmpfs skeleton for {} at {}",
@@ -460,8 +588,8 @@ impl MagicMount {
                             &self.path,
                             &self.work_dir_path,
                             has_tmpfs,
-                            #[cfg(any(target_os = "linux", target_os = "android"))]
                             self.umount,
+                            self.roots,
                         )
                         .do_magic_mount()
                         .with_context(|| format!("magic mount {}/{name}", self.path.display()))
@@ -504,8 +632,8 @@ impl MagicMount {
                 &self.path,
                 &self.work_dir_path,
                 has_tmpfs,
-                #[cfg(any(target_os = "linux", target_os = "android"))]
                 self.umount,
+                self.roots,
             )
             .do_magic_mount()
             .with_context(|| format!("magic mount {}/{name}", self.path.display()))
@@ -543,12 +671,28 @@ impl MagicMount {
                     )
                 })?;
 
+            journal::record(
+                MountRecord::new(
+                    &self.path,
+                    "tmpfs",
+                    &self
+                        .node
+                        .module_path
+                        .as_ref()
+                        .map(|p| p.to_string_lossy().to_string())
+                        .unwrap_or_default(),
+                    MountRole::Magic,
+                )
+                .options("bind,ro")
+                .contributors(contributors),
+            );
+
             if let Err(e) = mount_change(&self.path, MountPropagationFlags::PRIVATE) {
                 log::warn!("make dir {} private: {e:#?}", self.path.display());
             }
 
             #[cfg(any(target_os = "linux", target_os = "android"))]
-            if self.umount {
+            if self.umount.wants(&self.node) {
                 let _ = send_unmountable(&self.path);
             }
         }
@@ -584,11 +728,10 @@ pub fn mount_partitions(
     module_paths: &[PathBuf],
     mount_source: &str,
     extra_partitions: &[String],
-    exclusions: HashMap<PathBuf, HashSet<String>>,
-    #[cfg(any(target_os = "linux", target_os = "android"))] disable_umount: bool,
-    #[cfg(not(any(target_os = "linux", target_os = "android")))] _disable_umount: bool,
+    scopes: &HashMap<PathBuf, MagicScope>,
+    disable_umount: bool,
 ) -> Result<()> {
-    if let Some(root) = collect_module_files(module_paths, extra_partitions, &exclusions)? {
+    if let Some(root) = collect_module_files(module_paths, extra_partitions, scopes)? {
         log::debug!("[Magic Mount Tree Constructed]");
 
         let tree_str = format!("{:?}", root);
@@ -618,8 +761,11 @@ pub fn mount_partitions(
                 Path::new("/"),
                 tmp_dir.as_path(),
                 false,
-                #[cfg(any(target_os = "linux", target_os = "android"))]
-                !disable_umount,
+                UmountRules {
+                    default: !disable_umount,
+                    scopes,
+                },
+                module_paths,
             )
             .do_magic_mount()
         };
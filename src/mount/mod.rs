@@ -1,6 +1,7 @@
 // Copyright 2025 Meta-Hybrid Mount Authors
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+pub mod journal;
 pub mod magic;
 pub mod node;
 pub mod overlay;
@@ -5,13 +5,12 @@ use std::{
     collections::HashMap,
     fmt,
     fs::FileType,
-    path::{Component, PathBuf},
+    path::{Component, Path, PathBuf},
 };
 
 use crate::core::modules::ModuleFile;
 
 #[derive(PartialEq, Eq, Hash, Clone, Debug, Copy)]
-
 pub enum NodeFileType {
     RegularFile,
     Directory,
@@ -43,7 +42,6 @@ impl fmt::Display for NodeFileType {
 }
 
 #[derive(Clone)]
-
 pub struct Node {
     pub name: String,
     pub file_type: NodeFileType,
@@ -146,7 +144,12 @@ impl Node {
         }
     }
 
-    pub fn collect_module_files(&mut self, root: &PathBuf) -> anyhow::Result<()> {
+    /// Adds every entry below `root` accepted by `keep`, which receives the
+    /// path relative to `root`.
+    pub fn collect_module_files<F>(&mut self, root: &PathBuf, keep: F) -> anyhow::Result<()>
+    where
+        F: Fn(&Path) -> bool,
+    {
         for entry in walkdir::WalkDir::new(root)
             .min_depth(1)
             .into_iter()
@@ -156,6 +159,10 @@ impl Node {
 
             let relative_path = path.strip_prefix(root)?;
 
+            if !keep(relative_path) {
+                continue;
+            }
+
             let module_file = ModuleFile::new(root, relative_path)?;
 
             if module_file.is_replace_file {
@@ -18,9 +18,12 @@ use rustix::{
     mount::*,
 };
 
-use crate::defs::{KSU_OVERLAY_SOURCE, RUN_DIR};
 #[cfg(any(target_os = "linux", target_os = "android"))]
 use crate::try_umount::send_unmountable;
+use crate::{
+    defs::{KSU_OVERLAY_SOURCE, RUN_DIR},
+    mount::journal::{self, MountRecord, MountRole},
+};
 
 const PAGE_LIMIT: usize = 4000;
 
@@ -73,6 +76,28 @@ fn get_overlay_features() -> String {
     features
 }
 
+fn overlay_options(upperdir: &Option<PathBuf>, workdir: &Option<PathBuf>) -> String {
+    let mut options = Vec::new();
+
+    if let (Some(upperdir), Some(workdir)) = (
+        upperdir.as_ref().filter(|up| up.exists()),
+        workdir.as_ref().filter(|wd| wd.exists()),
+    ) {
+        options.push(format!("upperdir={}", upperdir.display()));
+
+        options.push(format!("workdir={}", workdir.display()));
+    }
+
+    options.extend(
+        get_overlay_features()
+            .split(',')
+            .filter(|s| !s.is_empty())
+            .map(String::from),
+    );
+
+    options.join(",")
+}
+
 fn get_sub_mounts(parent: &str) -> Result<Vec<String>> {
     let file = fs::File::open("/proc/mounts").context("Failed to open /proc/mounts")?;
 
@@ -300,6 +325,17 @@ fn mount_overlayfs_staged(
         )?;
 
         if !is_last_layer {
+            journal::record(
+                MountRecord::new(
+                    &target_path,
+                    "overlay",
+                    KSU_OVERLAY_SOURCE,
+                    MountRole::Staging,
+                )
+                .partition(journal::partition_of(dest.as_ref()))
+                .lowerdirs(batch),
+            );
+
             guard.mounts.push(target_path.clone());
 
             current_base = target_path.to_string_lossy().to_string();
@@ -429,6 +465,13 @@ fn mount_overlay_child(
             }
         }
 
+        journal::record(MountRecord::new(
+            Path::new(mount_point),
+            "move",
+            "stash",
+            MountRole::ChildRestored,
+        ));
+
         #[cfg(any(target_os = "linux", target_os = "android"))]
         if !disable_umount {
             let _ = send_unmountable(mount_point);
@@ -492,12 +535,35 @@ fn mount_overlay_child(
             }
         }
 
+        journal::record(
+            MountRecord::new(
+                Path::new(mount_point),
+                "move",
+                "stash",
+                MountRole::ChildRestored,
+            )
+            .fallback(format!("overlay failed: {:#}", e)),
+        );
+
         #[cfg(any(target_os = "linux", target_os = "android"))]
         if !disable_umount {
             let _ = send_unmountable(mount_point);
         }
-    } else if let StashedMount::Legacy(path) = stock {
-        let _ = fs::remove_dir(path);
+    } else {
+        journal::record(
+            MountRecord::new(
+                Path::new(mount_point),
+                "overlay",
+                KSU_OVERLAY_SOURCE,
+                MountRole::ChildOverlaid,
+            )
+            .lowerdirs(&lower_dirs)
+            .options(overlay_options(&None, &None)),
+        );
+
+        if let StashedMount::Legacy(path) = stock {
+            let _ = fs::remove_dir(path);
+        }
     }
 
     Ok(())
@@ -535,6 +601,8 @@ pub fn mount_overlay(
         }
     }
 
+    let options = overlay_options(&upperdir, &workdir);
+
     let mut stashed_mounts = Vec::new();
 
     let stash_base = Path::new(RUN_DIR).join("stash");
@@ -604,6 +672,23 @@ pub fn mount_overlay(
     )
     .with_context(|| format!("mount overlayfs for root {target_root} failed"))?;
 
+    journal::record(
+        MountRecord::new(
+            Path::new(target_root),
+            "overlay",
+            KSU_OVERLAY_SOURCE,
+            MountRole::Overlay,
+        )
+        .lowerdirs(module_roots)
+        .options(options)
+        .stashed_children(
+            stashed_mounts
+                .iter()
+                .map(|(mount_point, _, _)| PathBuf::from(mount_point))
+                .collect(),
+        ),
+    );
+
     for (mount_point, relative, stock) in stashed_mounts {
         if let Err(e) = mount_overlay_child(
             &mount_point,
@@ -624,6 +709,8 @@ pub fn mount_overlay(
                     target_root,
                     umount_err
                 );
+            } else {
+                journal::revert(Path::new(target_root));
             }
 
             bail!("Child mount restoration failed: {}", e);
@@ -21,8 +21,10 @@ static DRIVER_FD: OnceLock<RawFd> = OnceLock::new();
 
 static SENT_UNMOUNTS: OnceLock<Mutex<HashSet<String>>> = OnceLock::new();
 
-#[repr(C)]
+/// Paths the driver accepted; a subset of `SENT_UNMOUNTS`.
+static REGISTERED: Mutex<Vec<String>> = Mutex::new(Vec::new());
 
+#[repr(C)]
 struct KsuAddTryUmount {
     arg: u64,
     flags: u32,
@@ -30,7 +32,6 @@ struct KsuAddTryUmount {
 }
 
 #[repr(C)]
-
 struct NukeExt4SysfsCmd {
     arg: u64,
 }
@@ -87,7 +88,7 @@ where
 
     set.insert(path_str.clone());
 
-    let path = CString::new(path_str)?;
+    let path = CString::new(path_str.as_str())?;
 
     let cmd = KsuAddTryUmount {
         arg: path.as_ptr() as u64,
@@ -105,9 +106,20 @@ where
         ksu_add_try_umount(fd, &cmd)?;
     }
 
+    REGISTERED.lock().unwrap().push(path_str);
+
     Ok(())
 }
 
+/// Paths the driver accepted for try-umount in this process, sorted.
+pub fn registered() -> Vec<String> {
+    let mut paths = REGISTERED.lock().unwrap().clone();
+
+    paths.sort();
+
+    paths
+}
+
 pub fn ksu_nuke_sysfs(target: &str) -> Result<()> {
     let c_path = CString::new(target)?;
 
@@ -129,7 +141,6 @@ pub fn ksu_nuke_sysfs(target: &str) -> Result<()> {
 }
 
 #[cfg(not(any(target_os = "linux", target_os = "android")))]
-
 pub fn ksu_nuke_sysfs(_target: &str) -> Result<()> {
     bail!("Not supported on this OS")
 }
@@ -6,7 +6,7 @@ use std::{
     fmt as std_fmt,
     fs::{self, File, create_dir_all, remove_dir_all, remove_file, write},
     io::Write,
-    os::unix::fs::{PermissionsExt, symlink},
+    os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt, lchown, symlink},
     path::{Path, PathBuf},
     process::{Command, Stdio},
     sync::OnceLock,
@@ -17,7 +17,7 @@ use anyhow::{Context, Result, bail};
 use procfs::process::Process;
 use regex_lite::Regex;
 use rustix::{
-    fs::ioctl_ficlone,
+    fs::{AtFlags, CWD, FileType, Mode, Timespec, Timestamps, ioctl_ficlone, mknodat, utimensat},
     mount::{MountFlags, mount},
 };
 use tracing::{Event, Subscriber};
@@ -38,13 +38,14 @@ use extattr::{Flags as XattrFlags, lsetxattr};
 const SELINUX_XATTR: &str = "security.selinux";
 
 #[allow(dead_code)]
-
 const XATTR_TEST_FILE: &str = ".xattr_test";
 
 const DEFAULT_CONTEXT: &str = "u:object_r:system_file:s0";
 
 const OVERLAY_TEST_XATTR: &str = "trusted.overlay.test";
 
+const PRESERVED_XATTR_PREFIXES: &[&str] = &[SELINUX_XATTR, "trusted.overlay.", "user."];
+
 static MODULE_ID_REGEX: OnceLock<Regex> = OnceLock::new();
 
 struct SimpleFormatter;
@@ -141,6 +142,65 @@ pub fn validate_module_id(module_id: &str) -> Result<()> {
     }
 }
 
+/// Reads an Android system property; `None` when unset or unavailable.
+pub fn getprop(name: &str) -> Option<String> {
+    let output = Command::new("getprop").arg(name).output().ok()?;
+
+    let value = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+    (!value.is_empty()).then_some(value)
+}
+
+/// Replaces `path` with `data` so readers see either the old or the new
+/// content, never a partial write.
+pub fn atomic_write<P: AsRef<Path>>(path: P, data: &[u8]) -> Result<()> {
+    let path = path.as_ref();
+
+    let tmp = path.with_extension(format!("tmp.{}", std::process::id()));
+
+    let result = (|| -> Result<()> {
+        let mut file = File::create(&tmp)?;
+
+        file.write_all(data)?;
+
+        file.sync_all()?;
+
+        fs::rename(&tmp, path)?;
+
+        if let Some(parent) = path.parent()
+            && let Ok(dir) = File::open(parent)
+        {
+            let _ = dir.sync_all();
+        }
+
+        Ok(())
+    })();
+
+    if result.is_err() {
+        let _ = remove_file(&tmp);
+    }
+
+    result.with_context(|| format!("Failed to write {}", path.display()))
+}
+
+pub const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+
+/// Folds `bytes` into a running FNV-1a hash. Cheap change detection, not a
+/// defence against tampering.
+pub fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for byte in bytes {
+        hash ^= *byte as u64;
+
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    hash
+}
+
+pub fn checksum(bytes: &[u8]) -> String {
+    format!("{:016x}", fnv1a(FNV_OFFSET, bytes))
+}
+
 pub fn check_zygisksu_enforce_status() -> bool {
     std::fs::read_to_string("/data/adb/zygisksu/denylist_enforce")
         .map(|s| s.trim() != "0")
@@ -173,7 +233,6 @@ pub fn lsetfilecon<P: AsRef<Path>>(path: P, con: &str) -> Result<()> {
 }
 
 #[cfg(any(target_os = "linux", target_os = "android"))]
-
 pub fn lgetfilecon<P: AsRef<Path>>(path: P) -> Result<String> {
     let con = extattr::lgetxattr(&path, SELINUX_XATTR).with_context(|| {
         format!(
@@ -186,7 +245,6 @@ pub fn lgetfilecon<P: AsRef<Path>>(path: P) -> Result<String> {
 }
 
 #[cfg(not(any(target_os = "linux", target_os = "android")))]
-
 pub fn lgetfilecon<P: AsRef<Path>>(_path: P) -> Result<String> {
     Ok(DEFAULT_CONTEXT.to_string())
 }
@@ -240,7 +298,6 @@ pub fn random_kworker_name() -> String {
 }
 
 #[allow(dead_code)]
-
 pub fn is_xattr_supported(path: &Path) -> bool {
     let test_file = path.join(XATTR_TEST_FILE);
 
@@ -412,17 +469,146 @@ pub fn reflink_or_copy(src: &Path, dest: &Path) -> Result<u64> {
     fs::copy(src, dest).map_err(|e| e.into())
 }
 
-fn native_cp_r(src: &Path, dst: &Path) -> Result<()> {
-    if !dst.exists() {
-        create_dir_all(dst)?;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn copy_xattrs(src: &Path, dst: &Path) -> Result<()> {
+    let names = match extattr::llistxattr(src) {
+        Ok(names) => names,
+        Err(e) => {
+            log::debug!(
+                "llistxattr {} failed: {}",
+                src.display(),
+                std::io::Error::from(e)
+            );
 
-        let src_meta = src.metadata()?;
+            Vec::new()
+        }
+    };
+
+    let mut has_context = false;
 
-        fs::set_permissions(dst, src_meta.permissions())?;
+    for name in names {
+        let name_str = name.to_string_lossy();
+
+        if !PRESERVED_XATTR_PREFIXES
+            .iter()
+            .any(|prefix| name_str.starts_with(prefix))
+        {
+            continue;
+        }
+
+        let Ok(value) = extattr::lgetxattr(src, &name) else {
+            continue;
+        };
+
+        match lsetxattr(dst, &name, &value, XattrFlags::empty()) {
+            Ok(_) => has_context |= name_str == SELINUX_XATTR,
+            Err(e) => log::debug!(
+                "lsetxattr {} on {} failed: {}",
+                name_str,
+                dst.display(),
+                std::io::Error::from(e)
+            ),
+        }
+    }
 
+    if !has_context {
         lsetfilecon(dst, DEFAULT_CONTEXT)?;
     }
 
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn copy_xattrs(_src: &Path, dst: &Path) -> Result<()> {
+    lsetfilecon(dst, DEFAULT_CONTEXT)
+}
+
+/// Applies owner, mode, preserved xattrs and timestamps of `src` to `dst`
+/// without following symlinks.
+pub fn copy_entry_metadata(src: &Path, dst: &Path) -> Result<()> {
+    let meta = fs::symlink_metadata(src)?;
+
+    if let Err(e) = lchown(dst, Some(meta.uid()), Some(meta.gid())) {
+        log::debug!("lchown {} failed: {}", dst.display(), e);
+    }
+
+    if !meta.file_type().is_symlink() {
+        fs::set_permissions(dst, meta.permissions())?;
+    }
+
+    copy_xattrs(src, dst)?;
+
+    let times = Timestamps {
+        last_access: Timespec {
+            tv_sec: meta.atime(),
+            tv_nsec: meta.atime_nsec() as _,
+        },
+        last_modification: Timespec {
+            tv_sec: meta.mtime(),
+            tv_nsec: meta.mtime_nsec() as _,
+        },
+    };
+
+    utimensat(CWD, dst, &times, AtFlags::SYMLINK_NOFOLLOW)
+        .with_context(|| format!("Failed to set timestamps on {}", dst.display()))?;
+
+    Ok(())
+}
+
+/// Creates `dst` as a directory carrying the metadata of `src`.
+/// Its timestamps must be reapplied once its children have been written.
+pub fn copy_dir_entry(src: &Path, dst: &Path) -> Result<()> {
+    create_dir_all(dst)?;
+
+    copy_entry_metadata(src, dst)
+}
+
+/// Copies a single non-directory entry, replacing whatever exists at `dst`.
+/// Whiteouts (char devices with rdev 0) and FIFOs are recreated; other
+/// special files are skipped. Returns the number of bytes copied.
+pub fn copy_file_entry(src: &Path, dst: &Path) -> Result<u64> {
+    let meta = fs::symlink_metadata(src)?;
+
+    let ft = meta.file_type();
+
+    if fs::symlink_metadata(dst).is_ok() {
+        remove_file(dst)?;
+    }
+
+    let mode = Mode::from_raw_mode(meta.mode() & 0o7777);
+
+    let len = if ft.is_symlink() {
+        let link_target = fs::read_link(src)?;
+
+        symlink(&link_target, dst)?;
+
+        0
+    } else if ft.is_char_device() && meta.rdev() == 0 {
+        mknodat(CWD, dst, FileType::CharacterDevice, mode, 0)
+            .with_context(|| format!("Failed to create whiteout {}", dst.display()))?;
+
+        0
+    } else if ft.is_fifo() {
+        mknodat(CWD, dst, FileType::Fifo, mode, 0)
+            .with_context(|| format!("Failed to create FIFO {}", dst.display()))?;
+
+        0
+    } else if ft.is_file() {
+        reflink_or_copy(src, dst)?
+    } else {
+        log::warn!("Skipping unsupported special file: {}", src.display());
+
+        return Ok(0);
+    };
+
+    copy_entry_metadata(src, dst)?;
+
+    Ok(len)
+}
+
+fn native_cp_r(src: &Path, dst: &Path) -> Result<()> {
+    create_dir_all(dst)?;
+
     for entry in fs::read_dir(src)? {
         let entry = entry?;
 
@@ -434,24 +620,12 @@ fn native_cp_r(src: &Path, dst: &Path) -> Result<()> {
 
         if ft.is_dir() {
             native_cp_r(&src_path, &dst_path)?;
-        } else if ft.is_symlink() {
-            let link_target = fs::read_link(&src_path)?;
-
-            if dst_path.exists() {
-                remove_file(&dst_path)?;
-            }
-
-            symlink(&link_target, &dst_path)?;
-
-            let _ = lsetfilecon(&dst_path, DEFAULT_CONTEXT);
         } else {
-            reflink_or_copy(&src_path, &dst_path)?;
-
-            lsetfilecon(&dst_path, DEFAULT_CONTEXT)?;
+            copy_file_entry(&src_path, &dst_path)?;
         }
     }
 
-    Ok(())
+    copy_entry_metadata(src, dst)
 }
 
 pub fn sync_dir(src: &Path, dst: &Path) -> Result<()> {
@@ -501,7 +675,6 @@ pub fn select_temp_dir() -> Result<PathBuf> {
 }
 
 #[allow(dead_code)]
-
 pub fn cleanup_temp_dir(temp_dir: &Path) {
     if let Err(e) = remove_dir_all(temp_dir) {
         log::warn!(
@@ -513,7 +686,6 @@ pub fn cleanup_temp_dir(temp_dir: &Path) {
 }
 
 #[allow(dead_code)]
-
 pub fn ensure_temp_dir(temp_dir: &Path) -> Result<()> {
     if temp_dir.exists() {
         remove_dir_all(temp_dir).ok();
@@ -5,7 +5,7 @@
 
 import { APP_VERSION } from './constants_gen';
 import { DEFAULT_CONFIG } from './constants';
-import type { AppConfig, DeviceInfo, Module, StorageStatus, SystemInfo, ModuleRules, ConflictEntry, DiagnosticIssue, Silo } from './types';
+import type { AppConfig, DeviceInfo, Module, StorageStatus, SystemInfo, ModuleRules, ConflictEntry, DiagnosticIssue, MountRecord, Silo, RatoonStatus, SiloDiff, CorruptSilo, RestoreScope } from './types';
 
 const delay = (ms: number) => new Promise(resolve => setTimeout(resolve, ms));
 
@@ -51,6 +51,7 @@ export const MockAPI = {
         description: 'This is a mock module for testing.',
         mode: 'magic',
         is_mounted: true,
+        priority: 0,
         rules: { 
             default_mode: 'magic', 
             paths: { "system/fonts": "overlay" } 
@@ -64,6 +65,7 @@ export const MockAPI = {
         description: 'Changes system colors.',
         mode: 'auto',
         is_mounted: true,
+        priority: 0,
         rules: { 
             default_mode: 'overlay', 
             paths: {} 
@@ -77,6 +79,7 @@ export const MockAPI = {
         description: 'This module is not mounted.',
         mode: 'ignore',
         is_mounted: false,
+        priority: 0,
         rules: {
             default_mode: 'ignore',
             paths: {}
@@ -131,7 +134,9 @@ export const MockAPI = {
       selinux: 'Enforcing',
       mountBase: '/data/adb/meta-hybrid/mnt',
       activeMounts: ['system', 'product'],
-      zygisksuEnforce: '1'
+      zygisksuEnforce: '1',
+      profile: 'daily',
+      umountPaths: ['/product', '/system']
     };
   },
   async fetchSystemColor(): Promise<string | null> {
@@ -150,6 +155,14 @@ export const MockAPI = {
           { level: "Warning", context: "magisk_module_1", message: "Dead absolute symlink: system/bin/test -> /dev/null" }
       ];
   },
+  async getMounts(): Promise<MountRecord[]> {
+      await delay(300);
+      return [
+          { order: 0, target: "/debug_ramdisk", fs_type: "tmpfs", source: "KSU", role: "storage", options: "mode=0755", result: "mounted" },
+          { order: 1, target: "/system", fs_type: "overlay", source: "KSU", role: "overlay", lowerdirs: ["/debug_ramdisk/overlay_module_2/system"], result: "mounted" },
+          { order: 2, target: "/vendor", fs_type: "overlay", source: "KSU", role: "overlay", lowerdirs: ["/debug_ramdisk/magisk_module_1/vendor"], result: "failed", error: "Invalid argument (os error 22)", fallback: "magic mount takes over vendor for magisk_module_1" }
+      ];
+  },
 
   async getGranaryList(): Promise<Silo[]> {
     await delay(400);
@@ -172,9 +185,44 @@ export const MockAPI = {
     mockSilos = mockSilos.filter(s => s.id !== siloId);
     console.log(`[Mock] Deleted silo: ${siloId}`);
   },
-  async restoreSilo(siloId: string): Promise<void> {
+  async restoreSilo(siloId: string, scope?: RestoreScope): Promise<void> {
     await delay(500);
-    console.log(`[Mock] Restored silo: ${siloId}`);
+    console.log(`[Mock] Restored silo: ${siloId}`, scope ?? 'full');
+  },
+
+  async pinSilo(siloId: string, pinned: boolean): Promise<void> {
+    await delay(300);
+    mockSilos = mockSilos.map(s => s.id === siloId ? { ...s, pinned } : s);
+    console.log(`[Mock] ${pinned ? 'Pinned' : 'Unpinned'} silo: ${siloId}`);
+  },
+  async diffSilo(from: string, to: string): Promise<SiloDiff> {
+    await delay(300);
+    return {
+      from,
+      to,
+      config: [{ key: 'verbose', from: false, to: true }],
+      winnowing: [],
+      rules: [{ key: 'magisk_module_1.default_mode', from: 'overlay', to: 'magic' }],
+      modules: [{ key: 'overlay_module_2', from: null, to: { version: '1.0', enabled: true, skip_mount: false } }]
+    };
+  },
+
+  async checkSilos(): Promise<CorruptSilo[]> {
+    await delay(200);
+    return [];
+  },
+
+  async getRatoonStatus(): Promise<RatoonStatus> {
+    await delay(200);
+    return {
+      notice: "Bootloop traced to module 'magisk_module_1' after 2 bisect rounds. It has been disabled; other modules were re-enabled.",
+      bisect: null
+    };
+  },
+
+  async dismissRescueNotice(): Promise<void> {
+    await delay(200);
+    console.log('[Mock] Rescue notice dismissed');
   },
 
   async setWinnowingRule(path: string, moduleId: string): Promise<void> {
@@ -6,7 +6,7 @@
 import { DEFAULT_CONFIG, PATHS } from './constants';
 import { APP_VERSION } from './constants_gen';
 import { MockAPI } from './api.mock';
-import type { AppConfig, Module, StorageStatus, SystemInfo, DeviceInfo, ModuleRules, ConflictEntry, DiagnosticIssue, Silo } from './types';
+import type { AppConfig, Module, StorageStatus, SystemInfo, DeviceInfo, ModuleRules, ConflictEntry, DiagnosticIssue, MountRecord, Silo, RatoonStatus, SiloDiff, CorruptSilo, RestoreScope } from './types';
 
 interface KsuExecResult {
   errno: number;
@@ -73,11 +73,17 @@ interface AppAPI {
   fetchSystemColor: () => Promise<string | null>;
   getConflicts: () => Promise<ConflictEntry[]>;
   getDiagnostics: () => Promise<DiagnosticIssue[]>;
+  getMounts: () => Promise<MountRecord[]>;
   reboot: () => Promise<void>;
   getGranaryList: () => Promise<Silo[]>;
   createSilo: (reason: string) => Promise<void>;
   deleteSilo: (siloId: string) => Promise<void>;
-  restoreSilo: (siloId: string) => Promise<void>;
+  restoreSilo: (siloId: string, scope?: RestoreScope) => Promise<void>;
+  pinSilo: (siloId: string, pinned: boolean) => Promise<void>;
+  diffSilo: (from: string, to: string) => Promise<SiloDiff>;
+  checkSilos: () => Promise<CorruptSilo[]>;
+  getRatoonStatus: () => Promise<RatoonStatus>;
+  dismissRescueNotice: () => Promise<void>;
   setWinnowingRule: (path: string, moduleId: string) => Promise<void>;
 }
 
@@ -171,6 +177,8 @@ const RealAPI: AppAPI = {
           const state = JSON.parse(outState);
           info.mountBase = state.mount_point || 'Unknown';
           info.activeMounts = state.active_mounts || [];
+          info.profile = state.profile;
+          info.umountPaths = state.umount_paths || [];
           if (state.zygisksu_enforce !== undefined) {
              info.zygisksuEnforce = state.zygisksu_enforce ? '1' : '0';
           }
@@ -241,6 +249,14 @@ const RealAPI: AppAPI = {
       } catch {}
       return [];
   },
+  getMounts: async (): Promise<MountRecord[]> => {
+      if (!ksuExec) return [];
+      try {
+          const { errno, stdout } = await ksuExec(`${PATHS.BINARY} status --mounts`);
+          if (errno === 0 && stdout) return JSON.parse(stdout);
+      } catch {}
+      return [];
+  },
   reboot: async (): Promise<void> => {
     if (!ksuExec) return;
     await ksuExec('reboot');
@@ -265,9 +281,49 @@ const RealAPI: AppAPI = {
     const { errno, stderr } = await ksuExec(cmd);
     if (errno !== 0) throw new Error(stderr);
   },
-  restoreSilo: async (siloId: string): Promise<void> => {
+  restoreSilo: async (siloId: string, scope?: RestoreScope): Promise<void> => {
+    if (!ksuExec) return;
+    let flags = '';
+    if (scope?.winnowing) flags += ' --winnowing';
+    if (scope?.partitions) flags += ' --partitions';
+    if (scope?.rules.length) flags += ` --rules "${scope.rules.join(',')}"`;
+    const cmd = `${PATHS.BINARY} granary restore "${siloId}"${flags}`;
+    const { errno, stderr } = await ksuExec(cmd);
+    if (errno !== 0) throw new Error(stderr);
+  },
+  pinSilo: async (siloId: string, pinned: boolean): Promise<void> => {
+    if (!ksuExec) return;
+    const action = pinned ? 'granary-pin' : 'granary-unpin';
+    const cmd = `${PATHS.BINARY} system-action --action ${action} --value "${siloId}"`;
+    const { errno, stderr } = await ksuExec(cmd);
+    if (errno !== 0) throw new Error(stderr);
+  },
+  diffSilo: async (from: string, to: string): Promise<SiloDiff> => {
+    if (!ksuExec) throw new Error("No KSU environment");
+    const cmd = `${PATHS.BINARY} granary diff "${from}" "${to}" --json`;
+    const { errno, stdout, stderr } = await ksuExec(cmd);
+    if (errno !== 0) throw new Error(stderr);
+    return JSON.parse(stdout);
+  },
+  checkSilos: async (): Promise<CorruptSilo[]> => {
+    if (!ksuExec) return [];
+    try {
+        const { stdout } = await ksuExec(`${PATHS.BINARY} granary check --json`);
+        if (stdout) return JSON.parse(stdout);
+    } catch {}
+    return [];
+  },
+  getRatoonStatus: async (): Promise<RatoonStatus> => {
+    if (!ksuExec) return { notice: null, bisect: null };
+    try {
+        const { errno, stdout } = await ksuExec(`${PATHS.BINARY} system-action --action ratoon-status`);
+        if (errno === 0 && stdout) return JSON.parse(stdout);
+    } catch {}
+    return { notice: null, bisect: null };
+  },
+  dismissRescueNotice: async (): Promise<void> => {
     if (!ksuExec) return;
-    const cmd = `${PATHS.BINARY} system-action --action granary-restore --value "${siloId}"`;
+    const cmd = `${PATHS.BINARY} system-action --action ratoon-dismiss`;
     const { errno, stderr } = await ksuExec(cmd);
     if (errno !== 0) throw new Error(stderr);
   },
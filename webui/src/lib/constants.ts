@@ -24,7 +24,9 @@ export const DEFAULT_CONFIG: AppConfig = {
   },
   granary: {
     max_backups: 20,
-    retention_days: 0
+    retention_days: 0,
+    ratoon_threshold: 3,
+    boot_timeout: 300
   }
 };
 
@@ -17,7 +17,8 @@ import type {
   LanguageOption,
   ModeStats,
   ConflictEntry,
-  DiagnosticIssue
+  DiagnosticIssue,
+  MountRecord
 } from './types';
 
 const localeModules = import.meta.glob('../locales/*.json', { eager: true });
@@ -63,6 +64,7 @@ const createStore = () => {
   let activePartitions = $state<string[]>([]);
   let conflicts = $state<ConflictEntry[]>([]);
   let diagnostics = $state<DiagnosticIssue[]>([]);
+  let mounts = $state<MountRecord[]>([]);
   
   let loadingConfig = $state(false);
   let loadingModules = $state(false);
@@ -247,6 +249,8 @@ const createStore = () => {
       diagnostics = await API.getDiagnostics();
       loadingDiagnostics = false;
 
+      mounts = await API.getMounts();
+
     } catch (e) {}
     loadingStatus = false;
   }
@@ -299,6 +303,7 @@ const createStore = () => {
     get conflicts() { return conflicts; },
     loadConflicts,
     get diagnostics() { return diagnostics; },
+    get mounts() { return mounts; },
     loadStatus,
     get loading() {
       return {
@@ -6,6 +6,9 @@
 export interface GranaryConfig {
   max_backups: number;
   retention_days: number;
+  ratoon_threshold?: number;
+  boot_timeout?: number;
+  bisect?: boolean;
 }
 
 export interface AppConfig {
@@ -22,26 +25,63 @@ export interface AppConfig {
   logfile?: string;
   winnowing?: Record<string, string>;
   granary: GranaryConfig;
+  priorities?: Record<string, number>;
+  checksum_sync?: boolean;
+  rollback_policy?: RollbackPolicy;
+  dependency_policy?: DependencyPolicy;
+  profile?: string;
+  profiles?: Record<string, MountProfile>;
 }
 
+export interface MountProfile {
+  modules: string[];
+  rules: Record<string, ModuleRules>;
+  partitions: string[];
+}
+
+export type RollbackPolicy = 'none' | 'partition' | 'global';
+
+export type DependencyPolicy = 'skip' | 'warn';
+
 export type MountMode = 'overlay' | 'magic' | 'ignore';
 
+export type UmountPolicy = 'default' | 'always' | 'never';
+
 export interface ModuleRules {
   default_mode: MountMode;
   paths: Record<string, MountMode>;
+  priority?: number;
+  requires?: string[];
+  conflicts?: string[];
+  replaces?: string[];
+  ignore_compat?: boolean;
+  umount?: UmountPolicy;
+  umount_paths?: Record<string, UmountPolicy>;
 }
 
 export interface Module {
   id: string;
   name: string;
   version: string;
+  version_code?: number;
   author: string;
   description: string;
+  prop?: Record<string, string>;
   mode: string;
   is_mounted: boolean;
+  priority: number;
   rules: ModuleRules;
   enabled?: boolean;
   source_path?: string;
+  mount_check?: MountCheck;
+  skip_reason?: string;
+}
+
+export interface MountCheck {
+  ok: number;
+  missing: number;
+  shadowed: number;
+  extra: number;
 }
 
 export interface StorageStatus {
@@ -59,6 +99,8 @@ export interface SystemInfo {
   mountBase: string;
   activeMounts: string[];
   zygisksuEnforce?: string;
+  profile?: string;
+  umountPaths?: string[];
 }
 
 export interface DeviceInfo {
@@ -91,6 +133,9 @@ export interface ConflictEntry {
   contending_modules: string[];
   selected?: string;
   is_forced?: boolean;
+  preferred?: string;
+  matched_rule?: string;
+  rule_kind?: 'exact' | 'prefix' | 'glob';
 }
 
 export interface Silo {
@@ -101,10 +146,79 @@ export interface Silo {
   config_snapshot: AppConfig;
   raw_config?: string;
   raw_state?: string;
+  modules?: SiloModule[];
+  rules?: Record<string, string>;
+  pinned?: boolean;
+  known_good?: boolean;
+  imported?: boolean;
+  last_seen?: number;
+  checksum?: string;
+}
+
+export interface RestoreScope {
+  winnowing: boolean;
+  partitions: boolean;
+  rules: string[];
+}
+
+export interface BisectState {
+  round: number;
+  modules: string[];
+  suspects: string[];
+  testing: string[];
+  booted: boolean;
+}
+
+export interface RatoonStatus {
+  notice: string | null;
+  bisect: BisectState | null;
+}
+
+export interface FieldChange {
+  key: string;
+  from: unknown;
+  to: unknown;
+}
+
+export interface SiloDiff {
+  from: string;
+  to: string;
+  config: FieldChange[];
+  winnowing: FieldChange[];
+  rules: FieldChange[] | null;
+  modules: FieldChange[] | null;
+}
+
+export interface CorruptSilo {
+  file: string;
+  error: string;
+}
+
+export interface SiloModule {
+  id: string;
+  version: string;
+  enabled: boolean;
+  skip_mount: boolean;
 }
 
 export interface DiagnosticIssue {
   level: 'Info' | 'Warning' | 'Critical';
   context: string;
   message: string;
+}
+
+export interface MountRecord {
+  order: number;
+  target: string;
+  fs_type: string;
+  source: string;
+  role: 'storage' | 'overlay' | 'staging' | 'child_restored' | 'child_overlaid' | 'magic';
+  partition?: string;
+  lowerdirs?: string[];
+  options?: string;
+  result: 'mounted' | 'failed' | 'reverted';
+  error?: string;
+  fallback?: string;
+  stashed_children?: string[];
+  contributors?: string[];
 }
\ No newline at end of file